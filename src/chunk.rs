@@ -0,0 +1,166 @@
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::limits::parse_size;
+
+/// Upper bound for one chunk of combined output: `100KB`, `50000` (bytes) or `8000tokens`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkSize {
+    Bytes(usize),
+    Tokens(usize),
+}
+
+impl FromStr for ChunkSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let trimmed = s.trim();
+        let lower = trimmed.to_ascii_lowercase();
+        let size = match lower
+            .strip_suffix("tokens")
+            .or_else(|| lower.strip_suffix("token"))
+        {
+            Some(number) => ChunkSize::Tokens(
+                number
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("invalid token count '{}'", s))?,
+            ),
+            None => ChunkSize::Bytes(parse_size(trimmed)? as usize),
+        };
+        match size {
+            ChunkSize::Bytes(0) | ChunkSize::Tokens(0) => {
+                Err(format!("chunk size '{}' must be greater than zero", s))
+            }
+            size => Ok(size),
+        }
+    }
+}
+
+/// Pack `pieces` into chunks measuring at most `limit` each, never splitting a piece.
+/// A piece longer than `limit` gets a chunk of its own; callers split big pieces first
+/// with [`line_runs`], in a way that keeps each one well-formed.
+pub fn split_chunks(
+    pieces: &[String],
+    limit: usize,
+    measure: &dyn Fn(&str) -> usize,
+) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    let mut used = 0;
+    for piece in pieces.iter().filter(|p| !p.is_empty()) {
+        let size = measure(piece);
+        if used > 0 && used + size > limit {
+            chunks.push(std::mem::take(&mut current));
+            used = 0;
+        }
+        current.push_str(piece);
+        used += size;
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Byte ranges of `text` holding whole lines, each measuring at most `limit` once
+/// `overhead` (what wraps every run) is added. A single line that doesn't fit gets a
+/// run of its own.
+pub fn line_runs(
+    text: &str,
+    limit: usize,
+    overhead: usize,
+    measure: &dyn Fn(&str) -> usize,
+) -> Vec<Range<usize>> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    let mut end = 0;
+    let mut used = overhead;
+    for line in text.split_inclusive('\n') {
+        let size = measure(line);
+        if end > start && used + size > limit {
+            runs.push(start..end);
+            start = end;
+            used = overhead;
+        }
+        end += line.len();
+        used += size;
+    }
+    if end > start || runs.is_empty() {
+        runs.push(start..end);
+    }
+    runs
+}
+
+/// Output file for chunk `n`: `{n}` in `template` is replaced, otherwise `-<n>` is
+/// added before the extension (`out.txt` becomes `out-1.txt`)
+pub fn chunk_path(template: &Path, n: usize) -> PathBuf {
+    let text = template.to_string_lossy();
+    if text.contains("{n}") {
+        return PathBuf::from(text.replace("{n}", &n.to_string()));
+    }
+    let stem = template
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let name = match template.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, n, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, n),
+    };
+    template.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chunk_size() {
+        assert_eq!("100KB".parse(), Ok(ChunkSize::Bytes(100 * 1024)));
+        assert_eq!("50000".parse(), Ok(ChunkSize::Bytes(50000)));
+        assert_eq!("8000tokens".parse(), Ok(ChunkSize::Tokens(8000)));
+        assert_eq!("1 token".parse(), Ok(ChunkSize::Tokens(1)));
+        assert!("0".parse::<ChunkSize>().is_err());
+        assert!("lots".parse::<ChunkSize>().is_err());
+    }
+
+    #[test]
+    fn test_split_chunks_between_pieces() {
+        let pieces = vec![
+            "aaaa\n".to_string(),
+            "bbbb\n".to_string(),
+            "c1\nc2\nc3\nc4\n".to_string(),
+            "dd\n".to_string(),
+        ];
+        let chunks = split_chunks(&pieces, 10, &|s: &str| s.len());
+        assert_eq!(chunks, vec!["aaaa\nbbbb\n", "c1\nc2\nc3\nc4\n", "dd\n"]);
+        assert_eq!(chunks.concat(), pieces.concat());
+    }
+
+    #[test]
+    fn test_line_runs() {
+        let text = "c1\nc2\nc3\nc4\nc5";
+        let runs = line_runs(text, 10, 4, &|s: &str| s.len());
+        let parts: Vec<&str> = runs.iter().map(|r| &text[r.clone()]).collect();
+        assert_eq!(parts, vec!["c1\nc2\n", "c3\nc4\n", "c5"]);
+
+        let long = "a very long line\nb\n";
+        let runs = line_runs(long, 5, 0, &|s: &str| s.len());
+        assert_eq!(runs, vec![0..17, 17..19]);
+        assert_eq!(line_runs("", 5, 0, &|s: &str| s.len()), vec![0..0]);
+    }
+
+    #[test]
+    fn test_chunk_path() {
+        assert_eq!(
+            chunk_path(Path::new("out-{n}.txt"), 2),
+            PathBuf::from("out-2.txt")
+        );
+        assert_eq!(
+            chunk_path(Path::new("dir/out.txt"), 3),
+            PathBuf::from("dir/out-3.txt")
+        );
+        assert_eq!(chunk_path(Path::new("out"), 1), PathBuf::from("out-1"));
+    }
+}
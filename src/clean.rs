@@ -1,8 +1,10 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
 use crate::config::{match_files_and_mark, parse_file_rules, Source};
+use crate::extract::match_selections;
+use crate::manifest::{manifest_key, Manifest, MANIFEST_FILE};
 
 /// Process a destination path and add it and potentially its contents to the keep list
 fn process_destination(
@@ -23,7 +25,7 @@ fn process_destination(
     }
     // If there are file rules, apply them
     else if let Some(file_rules) = files {
-        let rules = parse_file_rules(file_rules);
+        let rules = parse_file_rules(file_rules)?;
         let matches = match_files_and_mark(&full_dest, &rules);
         for (path, keep) in matches {
             if keep {
@@ -35,8 +37,41 @@ fn process_destination(
     Ok(())
 }
 
-/// Clean the context folder, removing files not specified in the configuration
-pub fn clean_context_folder(dest: &str, sources: &[Source], verbose: bool) -> Result<(), String> {
+/// Partial files recorded in the manifest that no rule selects the same way anymore
+fn stale_partial_files(
+    context_root: &Path,
+    sources: &[Source],
+    manifest: &Manifest,
+) -> Result<Vec<String>, String> {
+    let mut stale = Vec::new();
+    for source in sources {
+        let Some(record) = manifest.sources.get(source.name()) else {
+            continue;
+        };
+        let rules = parse_file_rules(source.files())?;
+        let current: HashMap<String, String> =
+            match_selections(&context_root.join(source.dest()), &rules)
+                .into_iter()
+                .map(|(path, selector)| (manifest_key(context_root, &path), selector.to_string()))
+                .collect();
+        for (path, selector) in &record.partial {
+            if current.get(path) != Some(selector) {
+                stale.push(path.clone());
+            }
+        }
+    }
+    Ok(stale)
+}
+
+/// Clean the context folder, removing files not specified in the configuration.
+/// Everything under the `dest` of an `untouched` source (one left out by a profile
+/// or selection) is kept as it is.
+pub fn clean_context_folder(
+    dest: &str,
+    sources: &[Source],
+    untouched: &[Source],
+    verbose: bool,
+) -> Result<(), String> {
     // Create destination directory if it doesn't exist
     std::fs::create_dir_all(dest)
         .map_err(|e| format!("Failed to create destination directory '{}': {}", dest, e))?;
@@ -46,9 +81,11 @@ pub fn clean_context_folder(dest: &str, sources: &[Source], verbose: bool) -> Re
     // Build a list of all files that should be kept
     let mut keep_files = HashSet::new();
 
-    // Always keep the root directory
+    // Always keep the root directory and the manifest
     let context_dir = Path::new(dest);
     keep_files.insert(context_dir.to_path_buf());
+    keep_files.insert(context_dir.join(MANIFEST_FILE));
+    let mut manifest = Manifest::load(context_dir)?;
 
     // Process each source to determine which files to keep
     for source in sources {
@@ -74,12 +111,26 @@ pub fn clean_context_folder(dest: &str, sources: &[Source], verbose: bool) -> Re
             } => {
                 process_destination(context_dir, src_dest, files.as_ref(), &mut keep_files)?;
             }
-            Source::Sh { dest: src_dest, .. } => {
-                process_destination(context_dir, src_dest, None, &mut keep_files)?;
+            Source::Sh {
+                dest: src_dest,
+                files,
+                ..
+            } => {
+                process_destination(context_dir, src_dest, files.as_ref(), &mut keep_files)?;
             }
         }
     }
 
+    for source in untouched {
+        process_destination(context_dir, source.dest(), None, &mut keep_files)?;
+    }
+
+    // Partial files trimmed by a selector that changed or was removed are stale
+    let stale = stale_partial_files(context_dir, sources, &manifest)?;
+    for path in &stale {
+        keep_files.remove(&context_dir.join(path));
+    }
+
     // Walk the context directory and remove files not in the keep list
     for entry in WalkDir::new(context_dir)
         .into_iter()
@@ -116,6 +167,16 @@ pub fn clean_context_folder(dest: &str, sources: &[Source], verbose: bool) -> Re
         }
     }
 
+    if context_dir.join(MANIFEST_FILE).exists() {
+        manifest
+            .sources
+            .retain(|name, _| sources.iter().chain(untouched).any(|s| s.name() == name));
+        for record in manifest.sources.values_mut() {
+            record.partial.retain(|path, _| !stale.contains(path));
+        }
+        manifest.save(context_dir)?;
+    }
+
     println!("Context folder cleaned successfully.");
     Ok(())
 }
@@ -163,10 +224,11 @@ mod tests {
             path: "dummy".to_string(),
             dest: "keep".to_string(),
             files: None,
+            options: Default::default(),
         }];
 
         // Run the clean function
-        clean_context_folder(context_dir.to_str().unwrap(), &sources, true).unwrap();
+        clean_context_folder(context_dir.to_str().unwrap(), &sources, &[], true).unwrap();
 
         // Verify keep files still exist
         assert!(context_dir.join("keep/file1.txt").exists());
@@ -201,10 +263,11 @@ mod tests {
             path: "dummy".to_string(),
             dest: "src".to_string(),
             files: Some(vec!["**/*.rs".to_string(), "!**/*.txt".to_string()]),
+            options: Default::default(),
         }];
 
         // Run the clean function
-        clean_context_folder(context_dir.to_str().unwrap(), &sources, true).unwrap();
+        clean_context_folder(context_dir.to_str().unwrap(), &sources, &[], true).unwrap();
 
         // Verify files that should be kept still exist
         assert!(context_dir.join("src/file1.rs").exists());
@@ -242,10 +305,11 @@ mod tests {
             path: "dummy".to_string(),
             dest: "keep".to_string(),
             files: None,
+            options: Default::default(),
         }];
 
         // Run the clean function
-        clean_context_folder(context_dir.to_str().unwrap(), &sources, true).unwrap();
+        clean_context_folder(context_dir.to_str().unwrap(), &sources, &[], true).unwrap();
 
         // Verify keep directory and its contents still exist
         assert!(context_dir.join("keep").exists());
@@ -294,10 +358,12 @@ mod tests {
             name: "test-script".to_string(),
             script: "echo 'test'".to_string(),
             dest: "script_output".to_string(),
+            files: None,
+            options: Default::default(),
         }];
 
         // Run the clean function
-        clean_context_folder(context_dir.to_str().unwrap(), &sources, true).unwrap();
+        clean_context_folder(context_dir.to_str().unwrap(), &sources, &[], true).unwrap();
 
         // Verify script_output and its contents are kept
         assert!(context_dir.join("script_output").exists());
@@ -311,5 +377,97 @@ mod tests {
         // Note: the clean function only removes empty directories
         // If other_dir is not empty after removing file.txt (e.g., due to hidden files)
         // it won't be removed, so we don't assert on the directory itself
+        // File rules, e.g. from the global exclude, apply to script output too
+        let sources = vec![crate::config::Source::Sh {
+            name: "test-script".to_string(),
+            script: "echo 'test'".to_string(),
+            dest: "script_output".to_string(),
+            files: Some(vec!["!**/*.log".to_string()]),
+            options: Default::default(),
+        }];
+        clean_context_folder(context_dir.to_str().unwrap(), &sources, &[], false).unwrap();
+        assert!(context_dir.join("script_output/result1.txt").exists());
+        assert!(!context_dir.join("script_output/result2.log").exists());
+    }
+
+    #[test]
+    fn test_clean_command_with_partial_files() {
+        let temp_dir = tempdir().unwrap();
+        let context_dir = temp_dir.path().join(".copilot-context");
+        create_test_files(&context_dir, &["lib/src/lib.rs", "lib/src/old.rs"]).unwrap();
+
+        let mut manifest = Manifest::default();
+        let mut record = crate::manifest::SourceRecord {
+            dest: "lib".to_string(),
+            ..Default::default()
+        };
+        record
+            .partial
+            .insert("lib/src/lib.rs".to_string(), "L1-10".to_string());
+        record
+            .partial
+            .insert("lib/src/old.rs".to_string(), "L1-10".to_string());
+        manifest.sources.insert("lib".to_string(), record);
+        manifest
+            .sources
+            .insert("removed".to_string(), Default::default());
+        manifest.save(&context_dir).unwrap();
+
+        // old.rs is now selected with a different range, so its partial copy is stale
+        let sources = vec![crate::config::Source::Path {
+            name: "lib".to_string(),
+            path: "dummy".to_string(),
+            dest: "lib".to_string(),
+            files: Some(vec![
+                "src/lib.rs#L1-10".to_string(),
+                "src/old.rs#L5-6".to_string(),
+            ]),
+            options: Default::default(),
+        }];
+
+        clean_context_folder(context_dir.to_str().unwrap(), &sources, &[], true).unwrap();
+
+        assert!(context_dir.join(MANIFEST_FILE).exists());
+        assert!(context_dir.join("lib/src/lib.rs").exists());
+        assert!(!context_dir.join("lib/src/old.rs").exists());
+
+        let manifest = Manifest::load(&context_dir).unwrap();
+        assert!(!manifest.sources.contains_key("removed"));
+        let partial = &manifest.sources["lib"].partial;
+        assert_eq!(partial.len(), 1);
+        assert_eq!(partial["lib/src/lib.rs"], "L1-10");
+    }
+
+    #[test]
+    fn test_clean_keeps_untouched_sources() {
+        let temp_dir = tempdir().unwrap();
+        let context_dir = temp_dir.path().join(".copilot-context");
+        create_test_files(&context_dir, &["quick/a.txt", "heavy/b.txt", "stray.txt"]).unwrap();
+        let mut manifest = Manifest::default();
+        manifest
+            .sources
+            .insert("heavy".to_string(), Default::default());
+        manifest.save(&context_dir).unwrap();
+
+        let source = |name: &str| crate::config::Source::Path {
+            name: name.to_string(),
+            path: "dummy".to_string(),
+            dest: name.to_string(),
+            files: None,
+            options: Default::default(),
+        };
+        clean_context_folder(
+            context_dir.to_str().unwrap(),
+            &[source("quick")],
+            &[source("heavy")],
+            true,
+        )
+        .unwrap();
+
+        assert!(context_dir.join("quick/a.txt").exists());
+        assert!(context_dir.join("heavy/b.txt").exists());
+        assert!(!context_dir.join("stray.txt").exists());
+        let manifest = Manifest::load(&context_dir).unwrap();
+        assert!(manifest.sources.contains_key("heavy"));
     }
 }
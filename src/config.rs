@@ -1,15 +1,283 @@
 use glob::Pattern;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::extract::Selector;
+use crate::format::OutputFormat;
+use crate::limits::Limits;
+use crate::migrate::CONFIG_VERSION;
+use crate::order::FileOrder;
+use crate::redact::RedactConfig;
+
+/// Context folder used when the config does not set `dest`.
+pub const DEFAULT_DEST: &str = ".copilot-context";
+
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
 pub struct ContextConfig {
     pub version: u8,
     pub dest: Option<String>,
+    /// Other config files (paths or URLs) whose sources and bundles are merged in
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<Include>,
+    /// Values for `${name}` placeholders in this file's sources
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vars: BTreeMap<String, String>,
+    /// Settings inherited by this file's sources, and those it includes, unless they set
+    /// their own
+    #[serde(default, skip_serializing_if = "SourceDefaults::is_default")]
+    pub defaults: SourceDefaults,
+    /// File rules removing matches from every source of this file and the files it
+    /// includes, e.g. `**/*.png`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
     pub sources: Vec<Source>,
+    /// Global limits: `max_file_size` and `binary` apply to sources that don't set their own,
+    /// `max_total_size` caps the whole context folder.
+    #[serde(flatten)]
+    pub limits: Limits,
+    /// Secret redaction applied to everything written to the context folder
+    #[serde(default, skip_serializing_if = "RedactConfig::is_default")]
+    pub redact: RedactConfig,
+    /// Named `combine` presets, run with `combine --bundle <name>`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bundles: Vec<Bundle>,
+    /// Named subsets of the sources, selected with `--profile <name>`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Directory containing the config file; relative paths are resolved against it.
+    #[serde(skip)]
+    pub base_dir: PathBuf,
+    /// Include each source was merged in from, by source name. Local sources are absent.
+    #[serde(skip)]
+    pub origins: BTreeMap<String, String>,
+    /// Directory of the file each included source was declared in, by source name.
+    /// `sh` sources run with it as their config directory.
+    #[serde(skip)]
+    pub source_dirs: BTreeMap<String, PathBuf>,
+}
+
+/// A saved `combine` invocation. Unset fields fall back to the command-line defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Bundle {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub patterns: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<OutputFormat>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tree: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub toc: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub with_headers: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub header_format: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub separator: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sort_files: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order: Option<FileOrder>,
+    /// Priority per glob; low priorities are dropped first to fit `max_tokens`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub priority: BTreeMap<String, i32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub grep: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncate: Option<bool>,
+    /// Output file, relative to the config file's directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clipboard: Option<bool>,
+}
+
+/// `[defaults]` section: settings every source inherits unless it sets its own
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SourceDefaults {
+    /// File rules for sources without `files`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<String>>,
+    /// Branch for repo sources without `branch`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    #[serde(flatten)]
+    pub limits: Limits,
+    /// HTTP headers for url sources; headers a source sets itself win
+    #[serde(default, skip_serializing_if = "Headers::is_empty")]
+    pub headers: Headers,
+}
+
+impl SourceDefaults {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// HTTP request headers. Values are hidden in debug output since they often hold tokens.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct Headers(pub BTreeMap<String, String>);
+
+impl Headers {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl std::fmt::Debug for Headers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.0.keys().map(|name| (name, "***")))
+            .finish()
+    }
+}
+
+/// An `include` entry: a path or URL, or a table that also sets `allow_exec`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Include {
+    Location(String),
+    Options {
+        /// Path or URL of the config file
+        url: String,
+        /// Let a remote config (and the configs it includes) add `sh` sources, which run
+        /// scripts, and `path` sources, which copy local files
+        #[serde(default)]
+        allow_exec: bool,
+    },
+}
+
+impl Include {
+    pub fn location(&self) -> &str {
+        match self {
+            Include::Location(location) | Include::Options { url: location, .. } => location,
+        }
+    }
+    pub fn allow_exec(&self) -> bool {
+        matches!(
+            self,
+            Include::Options {
+                allow_exec: true,
+                ..
+            }
+        )
+    }
+}
+
+/// A subset of the sources, chosen by name or tag
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Profile {
+    /// Sources to include by name
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sources: Vec<String>,
+    /// Sources to include when they have any of these tags
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Context folder for this profile, instead of the top-level `dest`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dest: Option<String>,
+}
+
+impl Profile {
+    pub fn selects(&self, source: &Source) -> bool {
+        self.sources.iter().any(|name| name == source.name())
+            || source
+                .options()
+                .tags
+                .iter()
+                .any(|tag| self.tags.contains(tag))
+    }
 }
 
 impl ContextConfig {
+    /// Resolve a path from the config against the config file's directory.
+    pub fn resolve_path(&self, path: &str) -> PathBuf {
+        resolve_path(&self.base_dir, path)
+    }
+    /// Absolute location of the context folder.
+    pub fn context_dir(&self) -> PathBuf {
+        self.resolve_path(self.dest.as_deref().unwrap_or(DEFAULT_DEST))
+    }
+    /// Fill in what each source leaves unset from `[defaults]`, and add `exclude` to
+    /// every source's file rules
+    pub fn apply_defaults(&mut self) {
+        let mut sources = std::mem::take(&mut self.sources);
+        for source in &mut sources {
+            self.apply_defaults_to(source);
+        }
+        self.sources = sources;
+    }
+    /// `apply_defaults` for one source, e.g. one merged in from an include
+    pub fn apply_defaults_to(&self, source: &mut Source) {
+        let defaults = &self.defaults;
+        let exclude = self.exclude.iter().map(|p| format!("!{}", p));
+        let options = source.options_mut();
+        let limits = &mut options.limits;
+        limits.max_file_size = limits.max_file_size.or(defaults.limits.max_file_size);
+        limits.max_total_size = limits.max_total_size.or(defaults.limits.max_total_size);
+        limits.binary = limits.binary.or(defaults.limits.binary);
+        let files = match source {
+            Source::Repo { branch, files, .. } => {
+                if branch.is_none() {
+                    branch.clone_from(&defaults.branch);
+                }
+                files
+            }
+            Source::Url { headers, files, .. } => {
+                for (name, value) in &defaults.headers.0 {
+                    headers
+                        .0
+                        .entry(name.clone())
+                        .or_insert_with(|| value.clone());
+                }
+                files
+            }
+            Source::Path { files, .. } | Source::Sh { files, .. } => files,
+        };
+        if files.is_none() {
+            files.clone_from(&defaults.files);
+        }
+        if !self.exclude.is_empty() {
+            files.get_or_insert_with(Vec::new).extend(exclude);
+        }
+    }
+    /// Keep only the sources selected by profile `name` and use its `dest`, if any.
+    /// Returns the sources that were left out.
+    pub fn apply_profile(&mut self, name: &str) -> Result<Vec<Source>, String> {
+        let profile = self.profiles.get(name).cloned().ok_or_else(|| {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            format!(
+                "unknown profile '{}' (available: {})",
+                name,
+                known.join(", ")
+            )
+        })?;
+        if let Some(missing) = profile
+            .sources
+            .iter()
+            .find(|n| !self.sources.iter().any(|s| s.name() == n.as_str()))
+        {
+            return Err(format!(
+                "profile '{}' names unknown source '{}'",
+                name, missing
+            ));
+        }
+        let (selected, skipped) = std::mem::take(&mut self.sources)
+            .into_iter()
+            .partition(|s| profile.selects(s));
+        self.sources = selected;
+        if profile.dest.is_some() {
+            self.dest = profile.dest;
+        }
+        Ok(skipped)
+    }
     pub fn add_source(&mut self, source: Source) {
         self.sources.push(source);
     }
@@ -18,6 +286,24 @@ impl ContextConfig {
         self.sources.retain(|src| src.name() != name);
         self.sources.len() < orig_len
     }
+    /// Turn a source on or off. Enabling removes the `enabled` key, since that's the default.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.sources.iter_mut().find(|s| s.name() == name) {
+            Some(src) => {
+                src.options_mut().enabled = if enabled { None } else { Some(false) };
+                true
+            }
+            None => false,
+        }
+    }
+    /// Remove disabled sources, returning them
+    pub fn take_disabled(&mut self) -> Vec<Source> {
+        let (enabled, disabled) = std::mem::take(&mut self.sources)
+            .into_iter()
+            .partition(|s| s.options().is_enabled());
+        self.sources = enabled;
+        disabled
+    }
     pub fn update_source(&mut self, name: &str, update: SourceUpdate) -> bool {
         if let Some(src) = self.sources.iter_mut().find(|s| s.name() == name) {
             src.apply_update(update);
@@ -79,29 +365,66 @@ pub fn make_source(
             branch,
             dest,
             files,
+            options: SourceOptions::default(),
         },
         "url" => Source::Url {
             name,
             url: url.expect("--url required for url kind"),
             dest,
             files,
+            headers: Headers::default(),
+            options: SourceOptions::default(),
         },
         "path" => Source::Path {
             name,
             path: path.expect("--path required for path kind"),
             dest,
             files,
+            options: SourceOptions::default(),
         },
         "sh" => Source::Sh {
             name,
             script: script.expect("--script required for sh kind"),
             dest,
+            files,
+            options: SourceOptions::default(),
         },
         _ => panic!("Unknown kind: {}", kind),
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Settings shared by every kind of source
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SourceOptions {
+    #[serde(flatten)]
+    pub limits: Limits,
+    /// Keep only files with a line matching one of these regexes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub content_include: Vec<String>,
+    /// Drop files with a line matching any of these regexes
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub content_exclude: Vec<String>,
+    /// Weight of this source's files in `combine`; low priorities are dropped first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<i32>,
+    /// Labels used by `[profiles]` to pick sources
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Sources that must be synced before this one, e.g. the repo an `sh` script reads
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    /// `false` keeps the source in the config but skips it when syncing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+}
+
+impl SourceOptions {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled != Some(false)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum Source {
     Repo {
@@ -110,23 +433,36 @@ pub enum Source {
         branch: Option<String>,
         dest: String,
         files: Option<Vec<String>>,
+        #[serde(flatten)]
+        options: SourceOptions,
     },
     Url {
         name: String,
         url: String,
         dest: String,
         files: Option<Vec<String>>,
+        /// Extra HTTP headers for the request, e.g. `Authorization`
+        #[serde(default, skip_serializing_if = "Headers::is_empty")]
+        headers: Headers,
+        #[serde(flatten)]
+        options: SourceOptions,
     },
     Path {
         name: String,
         path: String,
         dest: String,
         files: Option<Vec<String>>,
+        #[serde(flatten)]
+        options: SourceOptions,
     },
     Sh {
         name: String,
         script: String,
         dest: String,
+        /// Rules applied to the files the script writes under `dest`
+        files: Option<Vec<String>>,
+        #[serde(flatten)]
+        options: SourceOptions,
     },
 }
 
@@ -139,6 +475,108 @@ impl Source {
             Source::Sh { name, .. } => name,
         }
     }
+    /// Destination inside the context folder
+    pub fn dest(&self) -> &str {
+        match self {
+            Source::Repo { dest, .. } => dest,
+            Source::Url { dest, .. } => dest,
+            Source::Path { dest, .. } => dest,
+            Source::Sh { dest, .. } => dest,
+        }
+    }
+    /// The `type` of the source as written in the config
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Source::Repo { .. } => "repo",
+            Source::Url { .. } => "url",
+            Source::Path { .. } => "path",
+            Source::Sh { .. } => "sh",
+        }
+    }
+    /// File rules of the source
+    pub fn files(&self) -> &[String] {
+        match self {
+            Source::Repo { files, .. }
+            | Source::Url { files, .. }
+            | Source::Path { files, .. }
+            | Source::Sh { files, .. } => files.as_deref().unwrap_or_default(),
+        }
+    }
+    /// Where the source's content comes from: repo URL, URL or local path
+    pub fn origin(&self) -> Option<&str> {
+        match self {
+            Source::Repo { repo, .. } => Some(repo),
+            Source::Url { url, .. } => Some(url),
+            Source::Path { path, .. } => Some(path),
+            Source::Sh { .. } => None,
+        }
+    }
+    /// Every string value of the source except an `sh` script, for variable expansion
+    pub fn strings_mut(&mut self) -> Vec<&mut String> {
+        let (mut fields, files, options): (Vec<&mut String>, _, _) = match self {
+            Source::Repo {
+                name,
+                repo,
+                branch,
+                dest,
+                files,
+                options,
+            } => (
+                [name, repo, dest].into_iter().chain(branch).collect(),
+                files.as_mut(),
+                options,
+            ),
+            Source::Url {
+                name,
+                url,
+                dest,
+                files,
+                headers,
+                options,
+            } => (
+                [name, url, dest]
+                    .into_iter()
+                    .chain(headers.0.values_mut())
+                    .collect(),
+                files.as_mut(),
+                options,
+            ),
+            Source::Path {
+                name,
+                path,
+                dest,
+                files,
+                options,
+            } => (vec![name, path, dest], files.as_mut(), options),
+            Source::Sh {
+                name,
+                dest,
+                files,
+                options,
+                ..
+            } => (vec![name, dest], files.as_mut(), options),
+        };
+        fields.extend(files.into_iter().flatten());
+        fields.extend(options.content_include.iter_mut());
+        fields.extend(options.content_exclude.iter_mut());
+        fields
+    }
+    pub fn options(&self) -> &SourceOptions {
+        match self {
+            Source::Repo { options, .. } => options,
+            Source::Url { options, .. } => options,
+            Source::Path { options, .. } => options,
+            Source::Sh { options, .. } => options,
+        }
+    }
+    pub fn options_mut(&mut self) -> &mut SourceOptions {
+        match self {
+            Source::Repo { options, .. } => options,
+            Source::Url { options, .. } => options,
+            Source::Path { options, .. } => options,
+            Source::Sh { options, .. } => options,
+        }
+    }
     pub fn apply_update(&mut self, update: SourceUpdate) {
         match self {
             Source::Repo {
@@ -187,13 +625,21 @@ impl Source {
                     *files = Some(f);
                 }
             }
-            Source::Sh { script, dest, .. } => {
+            Source::Sh {
+                script,
+                dest,
+                files,
+                ..
+            } => {
                 if let Some(s) = update.script {
                     *script = s;
                 }
                 if let Some(d) = update.dest {
                     *dest = d;
                 }
+                if let Some(f) = update.files {
+                    *files = Some(f);
+                }
             }
         }
     }
@@ -203,17 +649,88 @@ impl Source {
 pub enum FileRule {
     Keep(Pattern),
     Delete(Pattern),
+    /// Keep matching files, trimmed down to the selected lines or section
+    Select(Pattern, Selector),
+    /// `path#Title` without `heading:`: keeps the literal path when a file matches it
+    /// (`issue#12.md`), otherwise `path` trimmed to the heading `Title`
+    KeepOrSelect(Pattern, Pattern, Selector),
 }
 
-pub fn parse_file_rules(files: &[String]) -> Vec<FileRule> {
+/// Parse `files` rules: `glob` keeps, `!glob` deletes and `glob#selector` keeps part of
+/// a file. Invalid globs and selectors are errors.
+pub fn parse_file_rules(files: &[String]) -> Result<Vec<FileRule>, String> {
+    let pattern =
+        |p: &str| Pattern::new(p).map_err(|e| format!("invalid file rule '{}': {}", p, e));
     files
         .iter()
         .map(|s| {
             if let Some(rest) = s.strip_prefix('!') {
-                FileRule::Delete(Pattern::new(rest).unwrap())
-            } else {
-                FileRule::Keep(Pattern::new(s).unwrap())
+                return Ok(FileRule::Delete(pattern(rest)?));
+            }
+            if let Some((path, selector)) = s.rsplit_once('#') {
+                if let Some(selector) =
+                    Selector::parse(selector).map_err(|e| format!("file rule '{}': {}", s, e))?
+                {
+                    // An empty pattern selects from every file, e.g. `#L1-50` on a url source
+                    let path = if path.is_empty() { "*" } else { path };
+                    return Ok(FileRule::Select(pattern(path)?, selector));
+                }
+                // `README.md#Installation` names a heading unless such a file exists
+                let title = selector.trim();
+                if !title.is_empty() && !title.contains(['/', '*', '?', '[']) {
+                    let path = if path.is_empty() { "*" } else { path };
+                    return Ok(FileRule::KeepOrSelect(
+                        pattern(s)?,
+                        pattern(path)?,
+                        Selector::Heading(title.to_string()),
+                    ));
+                }
+            }
+            Ok(FileRule::Keep(pattern(s)?))
+        })
+        .collect()
+}
+
+/// Path of `path` as matched by file rules under `root`. When `root` is itself
+/// a file (e.g. a downloaded url), rules are matched against its file name.
+pub fn rule_relative_path(root: &Path, path: &Path) -> String {
+    let base = if root.is_file() {
+        root.parent().unwrap_or(root)
+    } else {
+        root
+    };
+    path.strip_prefix(base)
+        .unwrap_or(path)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// `rules` with each `KeepOrSelect` settled against the files under `root`
+pub fn resolve_rules(root: &Path, rules: &[FileRule]) -> Vec<FileRule> {
+    if !rules
+        .iter()
+        .any(|rule| matches!(rule, FileRule::KeepOrSelect(..)))
+    {
+        return rules.to_vec();
+    }
+    let min_depth = if root.is_file() { 0 } else { 1 };
+    let paths: Vec<String> = WalkDir::new(root)
+        .min_depth(min_depth)
+        .into_iter()
+        .filter_map(Result::ok)
+        .map(|entry| rule_relative_path(root, entry.path()))
+        .collect();
+    rules
+        .iter()
+        .map(|rule| match rule {
+            FileRule::KeepOrSelect(literal, path, selector) => {
+                if paths.iter().any(|p| literal.matches(p)) {
+                    FileRule::Keep(literal.clone())
+                } else {
+                    FileRule::Select(path.clone(), selector.clone())
+                }
             }
+            other => other.clone(),
         })
         .collect()
 }
@@ -227,11 +744,12 @@ pub fn match_files_and_mark(
     if rules.is_empty() {
         return results;
     }
+    let rules = &resolve_rules(root, rules);
 
     let keep_patterns: Vec<&Pattern> = rules
         .iter()
         .filter_map(|r| match r {
-            FileRule::Keep(p) => Some(p),
+            FileRule::Keep(p) | FileRule::Select(p, _) => Some(p),
             _ => None,
         })
         .collect();
@@ -244,18 +762,15 @@ pub fn match_files_and_mark(
         })
         .collect();
 
-    for entry_result in WalkDir::new(root).min_depth(1).into_iter() {
+    // A file root is matched on its own; a directory root on its contents
+    let min_depth = if root.is_file() { 0 } else { 1 };
+    for entry_result in WalkDir::new(root).min_depth(min_depth).into_iter() {
         let entry = match entry_result {
             Ok(e) => e,
             Err(_) => continue,
         };
         let path = entry.path();
-
-        let rel_path = match path.strip_prefix(root) {
-            Ok(p) => p,
-            Err(_) => continue,
-        };
-        let rel_str = rel_path.to_string_lossy();
+        let rel_str = rule_relative_path(root, path);
 
         let mut should_be_kept: bool;
 
@@ -277,15 +792,83 @@ pub fn match_files_and_mark(
     results
 }
 
+/// Resolve `path` against `base`, expanding a leading `~` to the home directory.
+/// Absolute paths are returned unchanged.
+pub fn resolve_path(base: &Path, path: &str) -> PathBuf {
+    let expanded = expand_home(path);
+    if expanded.is_absolute() {
+        expanded
+    } else {
+        base.join(expanded)
+    }
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    let rest = if path == "~" {
+        Some("")
+    } else {
+        path.strip_prefix("~/").or_else(|| path.strip_prefix("~\\"))
+    };
+    if let Some(rest) = rest {
+        let home = std::env::var_os("HOME")
+            .or_else(|| std::env::var_os("USERPROFILE"))
+            .filter(|h| !h.is_empty());
+        if let Some(home) = home {
+            return PathBuf::from(home).join(rest);
+        }
+    }
+    PathBuf::from(path)
+}
+
+/// Absolute directory of the config file at `path`.
+fn config_base_dir(path: &str) -> std::io::Result<PathBuf> {
+    let parent = match Path::new(path).parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    std::fs::canonicalize(parent)
+}
+
+/// Load the config with defaults applied, variables expanded and its includes merged in
 pub fn load_config(path: &str) -> Result<ContextConfig, Box<dyn std::error::Error>> {
+    let mut config = load_config_raw(path)?;
+    crate::vars::interpolate_config(&mut config)?;
+    config.apply_defaults();
+    crate::include::resolve_includes(&mut config, Path::new(path))?;
+    crate::deps::check_dependencies(&config.sources)?;
+    for source in &config.sources {
+        parse_file_rules(source.files())
+            .map_err(|e| format!("source '{}': {}", source.name(), e))?;
+    }
+    Ok(config)
+}
+
+/// Load only the file itself, without its includes and with `${...}` templates left
+/// as written. Use this for configs that are saved again.
+pub fn load_config_raw(path: &str) -> Result<ContextConfig, Box<dyn std::error::Error>> {
     let f = std::fs::read_to_string(path)?;
-    let config: ContextConfig = toml::from_str(&f)?;
+    crate::migrate::check_version(&f).map_err(|e| format!("{}: {}", path, e))?;
+    let mut config: ContextConfig = toml::from_str(&f)?;
+    config.base_dir = config_base_dir(path)?;
     Ok(config)
 }
 
+/// JSON Schema for `context.toml`, for editor validation and completion
+pub fn config_schema() -> String {
+    let schema = schemars::schema_for!(ContextConfig);
+    serde_json::to_string_pretty(&schema).expect("schema serializes to JSON")
+}
+
+/// Write `config` to `path`. An existing file is edited in place so its comments and
+/// layout survive.
 pub fn save_config(path: &str, config: &ContextConfig) -> Result<(), Box<dyn std::error::Error>> {
     let toml = toml::to_string_pretty(config)?;
-    std::fs::write(path, toml)?;
+    let text = match std::fs::read_to_string(path) {
+        Ok(existing) => crate::edit::update_toml(&existing, &toml)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => toml,
+        Err(e) => return Err(e.into()),
+    };
+    std::fs::write(path, text)?;
     Ok(())
 }
 
@@ -295,8 +878,8 @@ pub fn write_default_config_if_missing(path: &str) -> Result<bool, Box<dyn std::
         return Ok(false);
     }
     let default = ContextConfig {
-        version: 1,
-        dest: Some(".copilot-context".to_string()),
+        version: CONFIG_VERSION,
+        dest: Some(DEFAULT_DEST.to_string()),
         sources: vec![
             Source::Repo {
                 name: "example-repo".to_string(),
@@ -304,26 +887,33 @@ pub fn write_default_config_if_missing(path: &str) -> Result<bool, Box<dyn std::
                 branch: Some("main".to_string()),
                 dest: "vendor/example-repo".to_string(),
                 files: Some(vec!["*".to_string()]),
+                options: SourceOptions::default(),
             },
             Source::Url {
                 name: "example-url".to_string(),
                 url: "https://example.com/file.txt".to_string(),
                 dest: "example/file.txt".to_string(),
                 files: None,
+                headers: Default::default(),
+                options: SourceOptions::default(),
             },
             Source::Path {
                 name: "local-notes".to_string(),
                 path: "README.md".to_string(),
                 dest: "vendor/notes/README.md".to_string(),
                 files: None,
+                options: SourceOptions::default(),
             },
             Source::Sh {
                 name: "example-script".to_string(),
                 script: "echo \'Hello from example script!\'\necho \'Current directory: $(pwd)\'"
                     .to_string(),
                 dest: ".".to_string(),
+                files: None,
+                options: SourceOptions::default(),
             },
         ],
+        ..Default::default()
     };
     save_config(path, &default)?;
     Ok(true)
@@ -332,6 +922,7 @@ pub fn write_default_config_if_missing(path: &str) -> Result<bool, Box<dyn std::
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::limits::BinaryPolicy;
     use std::fs;
     use tempfile::tempdir;
 
@@ -341,7 +932,8 @@ mod tests {
             "*".to_string(),
             "!foo.log".to_string(),
             "bar.txt".to_string(),
-        ]);
+        ])
+        .unwrap();
         assert_eq!(rules.len(), 3);
     }
 
@@ -360,7 +952,8 @@ mod tests {
             "!foo.log".to_string(),
             "*.txt".to_string(),
             "*.md".to_string(),
-        ]);
+        ])
+        .unwrap();
 
         let results = match_files_and_mark(dir.path(), &rules)
             .into_iter()
@@ -385,7 +978,7 @@ mod tests {
         assert!(delete.contains(&"foo.log".to_string()));
 
         // Test with only keep patterns - only matching files should be kept
-        let rules = parse_file_rules(&["*.txt".to_string()]);
+        let rules = parse_file_rules(&["*.txt".to_string()]).unwrap();
         let results = match_files_and_mark(dir.path(), &rules)
             .into_iter()
             .filter(|(p, _)| p.parent() == Some(dir.path()) && p.is_file())
@@ -409,7 +1002,7 @@ mod tests {
         assert!(!keep.contains(&"foo.log".to_string()));
 
         // Test with only delete patterns - all non-matching files should be kept
-        let rules = parse_file_rules(&["!*.txt".to_string()]);
+        let rules = parse_file_rules(&["!*.txt".to_string()]).unwrap();
         let results = match_files_and_mark(dir.path(), &rules)
             .into_iter()
             .filter(|(p, _)| p.parent() == Some(dir.path()) && p.is_file())
@@ -442,29 +1035,36 @@ mod tests {
                 branch: Some("main".to_string()),
                 dest: "vendor/repo1".to_string(),
                 files: Some(vec!["*".to_string()]),
+                options: SourceOptions::default(),
             },
             Source::Url {
                 name: "url1".to_string(),
                 url: "https://example.com/file.txt".to_string(),
                 dest: "file.txt".to_string(),
                 files: None,
+                headers: Default::default(),
+                options: SourceOptions::default(),
             },
             Source::Path {
                 name: "path1".to_string(),
                 path: "README.md".to_string(),
                 dest: "notes/README.md".to_string(),
                 files: None,
+                options: SourceOptions::default(),
             },
             Source::Sh {
                 name: "script1".to_string(),
                 script: "echo \"hello world\"".to_string(),
                 dest: "scripts".to_string(),
+                files: None,
+                options: SourceOptions::default(),
             },
         ];
         let config = ContextConfig {
             version: 1,
             dest: Some(".copilot-context".to_string()),
             sources: sources.clone(),
+            ..Default::default()
         };
         let toml = toml::to_string_pretty(&config).unwrap();
         let parsed: ContextConfig = toml::from_str(&toml).unwrap();
@@ -478,6 +1078,7 @@ mod tests {
                 branch,
                 dest,
                 files,
+                ..
             } => {
                 assert_eq!(name, "repo1");
                 assert_eq!(repo, "https://github.com/example/repo.git");
@@ -493,6 +1094,7 @@ mod tests {
                 url,
                 dest,
                 files,
+                ..
             } => {
                 assert_eq!(name, "url1");
                 assert_eq!(url, "https://example.com/file.txt");
@@ -507,6 +1109,7 @@ mod tests {
                 path,
                 dest,
                 files,
+                ..
             } => {
                 assert_eq!(name, "path1");
                 assert_eq!(path, "README.md");
@@ -516,7 +1119,9 @@ mod tests {
             _ => panic!("Expected path source"),
         }
         match &parsed.sources[3] {
-            Source::Sh { name, script, dest } => {
+            Source::Sh {
+                name, script, dest, ..
+            } => {
                 assert_eq!(name, "script1");
                 assert_eq!(script, "echo \"hello world\"");
                 assert_eq!(dest, "scripts");
@@ -525,6 +1130,83 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_limits_global_and_per_source() {
+        let toml = r#"
+version = 1
+max_file_size = "1MB"
+max_total_size = "50MB"
+binary = "skip"
+
+[[sources]]
+type = "repo"
+name = "models"
+repo = "https://github.com/example/models.git"
+dest = "vendor/models"
+max_file_size = 2048
+binary = "keep"
+"#;
+        let config: ContextConfig = toml::from_str(toml).unwrap();
+        assert_eq!(config.limits.max_file_size, Some(1024 * 1024));
+        assert_eq!(config.limits.max_total_size, Some(50 * 1024 * 1024));
+        assert_eq!(config.limits.binary, Some(BinaryPolicy::Skip));
+
+        let limits = &config.sources[0].options().limits;
+        assert_eq!(limits.max_file_size, Some(2048));
+        assert_eq!(limits.binary, Some(BinaryPolicy::Keep));
+
+        // Limits survive a save/load roundtrip
+        let serialized = toml::to_string_pretty(&config).unwrap();
+        let parsed: ContextConfig = toml::from_str(&serialized).unwrap();
+        assert_eq!(parsed.limits, config.limits);
+        assert_eq!(parsed.sources[0].options(), config.sources[0].options());
+    }
+
+    #[test]
+    fn test_redact_section() {
+        let config: ContextConfig = toml::from_str("version = 1\nsources = []\n").unwrap();
+        assert!(config.redact.enabled);
+        assert!(!config.redact.entropy);
+
+        let toml = r#"
+version = 1
+sources = []
+
+[redact]
+entropy = true
+patterns = ["DB_PASSWORD=(?P<secret>\\S+)"]
+"#;
+        let config: ContextConfig = toml::from_str(toml).unwrap();
+        assert!(config.redact.enabled);
+        assert!(config.redact.entropy);
+        assert_eq!(config.redact.patterns, vec![r"DB_PASSWORD=(?P<secret>\S+)"]);
+    }
+
+    #[test]
+    fn test_bundles_section() {
+        let toml = r#"
+version = 1
+sources = []
+
+[[bundles]]
+name = "api-review"
+patterns = ["src/**/*.rs", "docs/*.md"]
+format = "markdown"
+max_tokens = 8000
+output = "review.md"
+"#;
+        let config: ContextConfig = toml::from_str(toml).unwrap();
+        assert_eq!(config.bundles.len(), 1);
+        let bundle = &config.bundles[0];
+        assert_eq!(bundle.name, "api-review");
+        assert_eq!(bundle.format, Some(OutputFormat::Markdown));
+        assert_eq!(bundle.max_tokens, Some(8000));
+        assert_eq!(bundle.separator, None);
+
+        let roundtrip: ContextConfig = toml::from_str(&toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(roundtrip.bundles, config.bundles);
+    }
+
     #[test]
     fn test_save_and_load_config() {
         let dir = tempdir().unwrap();
@@ -538,7 +1220,9 @@ mod tests {
                 branch: None,
                 dest: "vendor/repo1".to_string(),
                 files: None,
+                options: SourceOptions::default(),
             }],
+            ..Default::default()
         };
         save_config(file_path.to_str().unwrap(), &config).unwrap();
         let loaded = load_config(file_path.to_str().unwrap()).unwrap();
@@ -556,7 +1240,7 @@ mod tests {
 
     #[test]
     fn test_file_rule_patterns() {
-        let rules = parse_file_rules(&["foo/*.rs".to_string(), "!foo/bar.rs".to_string()]);
+        let rules = parse_file_rules(&["foo/*.rs".to_string(), "!foo/bar.rs".to_string()]).unwrap();
         match &rules[0] {
             FileRule::Keep(pat) => assert!(pat.matches("foo/main.rs")),
             _ => panic!("Expected Keep pattern"),
@@ -567,20 +1251,106 @@ mod tests {
         }
 
         // Test with only keep patterns
-        let keep_rules = parse_file_rules(&["*.rs".to_string()]);
+        let keep_rules = parse_file_rules(&["*.rs".to_string()]).unwrap();
         let has_keep_patterns = keep_rules
             .iter()
             .any(|rule| matches!(rule, FileRule::Keep(_)));
         assert!(has_keep_patterns);
 
         // Test with only delete patterns
-        let delete_rules = parse_file_rules(&["!*.rs".to_string()]);
+        let delete_rules = parse_file_rules(&["!*.rs".to_string()]).unwrap();
         let has_keep_patterns = delete_rules
             .iter()
             .any(|rule| matches!(rule, FileRule::Keep(_)));
         assert!(!has_keep_patterns);
     }
 
+    #[test]
+    fn test_selector_rules() {
+        let rules = parse_file_rules(&[
+            "src/lib.rs#L100-220".to_string(),
+            "README.md#heading:Installation".to_string(),
+            "#L1-50".to_string(),
+        ])
+        .unwrap();
+        match &rules[0] {
+            FileRule::Select(pat, selector) => {
+                assert!(pat.matches("src/lib.rs"));
+                assert_eq!(selector, &Selector::Lines(100, 220));
+            }
+            _ => panic!("Expected Select rule"),
+        }
+        match &rules[1] {
+            FileRule::Select(pat, selector) => {
+                assert!(pat.matches("README.md"));
+                assert_eq!(selector, &Selector::Heading("Installation".to_string()));
+            }
+            _ => panic!("Expected Select rule"),
+        }
+        match &rules[2] {
+            FileRule::Select(pat, _) => assert!(pat.matches("anything.txt")),
+            _ => panic!("Expected Select rule"),
+        }
+
+        // A `#` followed by a path is part of the path
+        let rules = parse_file_rules(&["docs/C#/**".to_string()]).unwrap();
+        assert!(matches!(&rules[0], FileRule::Keep(p) if p.matches("docs/C#/intro.md")));
+
+        let err = parse_file_rules(&["src/lib.rs#L50-10".to_string()]).unwrap_err();
+        assert!(err.contains("invalid line range"), "{}", err);
+        assert!(parse_file_rules(&["src/[lib.rs".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_heading_without_prefix() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("README.md"),
+            "# Tool\n## Installation\ncargo install\n## Usage\nrun it\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("issue#12.md"), "bug").unwrap();
+        fs::write(dir.path().join("other.md"), "other").unwrap();
+
+        // `README.md#Installation` is a heading; `issue#12.md` is a file that exists
+        let rules = parse_file_rules(&[
+            "README.md#Installation".to_string(),
+            "issue#12.md".to_string(),
+        ])
+        .unwrap();
+        let kept: Vec<String> = match_files_and_mark(dir.path(), &rules)
+            .into_iter()
+            .filter(|(_, keep)| *keep)
+            .map(|(path, _)| rule_relative_path(dir.path(), &path))
+            .collect();
+        assert_eq!(kept.len(), 2, "{:?}", kept);
+        assert!(kept.contains(&"README.md".to_string()));
+        assert!(kept.contains(&"issue#12.md".to_string()));
+
+        let selections = crate::extract::match_selections(dir.path(), &rules);
+        assert_eq!(selections.len(), 1);
+        assert!(selections[0].0.ends_with("README.md"));
+        assert_eq!(
+            selections[0].1,
+            Selector::Heading("Installation".to_string())
+        );
+    }
+
+    #[test]
+    fn test_match_files_and_mark_file_root() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("spec.md");
+        fs::write(&file, "test").unwrap();
+
+        let results =
+            match_files_and_mark(&file, &parse_file_rules(&["*.md".to_string()]).unwrap());
+        assert_eq!(results, vec![(file.clone(), true)]);
+
+        let results =
+            match_files_and_mark(&file, &parse_file_rules(&["!spec.md".to_string()]).unwrap());
+        assert_eq!(results, vec![(file, false)]);
+    }
+
     #[test]
     fn test_match_files_and_mark_default_keep() {
         let dir = tempdir().unwrap();
@@ -588,12 +1358,12 @@ mod tests {
         fs::write(&file1, "test").unwrap();
 
         // Empty rules case
-        let rules = parse_file_rules(&[]); // No rules
+        let rules = parse_file_rules(&[]).unwrap(); // No rules
         let results = match_files_and_mark(dir.path(), &rules);
         assert!(results.is_empty()); // With no rules, no files should be processed
 
         // Only delete rules case - files not matching delete pattern should be kept
-        let rules = parse_file_rules(&["!bar.txt".to_string()]); // Only delete rule
+        let rules = parse_file_rules(&["!bar.txt".to_string()]).unwrap(); // Only delete rule
         let results = match_files_and_mark(dir.path(), &rules);
         let mut found = false;
         for (path, keep) in results {
@@ -611,12 +1381,339 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_load_config_sets_base_dir() {
+        let dir = tempdir().unwrap();
+        let nested = dir.path().join("other/dir");
+        fs::create_dir_all(&nested).unwrap();
+        let file_path = nested.join("context.toml");
+        fs::write(
+            &file_path,
+            "version = 1\ndest = \"build/ctx\"\nsources = []\n",
+        )
+        .unwrap();
+
+        let config = load_config(file_path.to_str().unwrap()).unwrap();
+        let expected_base = fs::canonicalize(&nested).unwrap();
+        assert_eq!(config.base_dir, expected_base);
+        assert_eq!(config.context_dir(), expected_base.join("build/ctx"));
+        assert_eq!(
+            config.resolve_path("README.md"),
+            expected_base.join("README.md")
+        );
+    }
+
+    #[test]
+    fn test_vars_expand_on_load_and_round_trip_on_save() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("context.toml");
+        let path = file_path.to_str().unwrap();
+        fs::write(
+            &file_path,
+            r#"
+version = 1
+
+[vars]
+version = "2.1"
+
+[[sources]]
+type = "repo"
+name = "lib"
+repo = "https://github.com/example/lib.git"
+branch = "v${version}"
+dest = "lib-${version}"
+files = ["docs/${version}/*.md"]
+"#,
+        )
+        .unwrap();
+
+        let config = load_config(path).unwrap();
+        match &config.sources[0] {
+            Source::Repo {
+                branch,
+                dest,
+                files,
+                ..
+            } => {
+                assert_eq!(branch.as_deref(), Some("v2.1"));
+                assert_eq!(dest, "lib-2.1");
+                assert_eq!(files.as_deref(), Some(&["docs/2.1/*.md".to_string()][..]));
+            }
+            other => panic!("unexpected source {:?}", other),
+        }
+
+        let mut raw = load_config_raw(path).unwrap();
+        let update = SourceUpdate::from_args(
+            None,
+            None,
+            None,
+            Some("vendor/lib-${version}".to_string()),
+            None,
+            None,
+            None,
+        );
+        raw.update_source("lib", update);
+        save_config(path, &raw).unwrap();
+        let saved = fs::read_to_string(&file_path).unwrap();
+        assert!(saved.contains("branch = \"v${version}\""), "{}", saved);
+        assert!(
+            saved.contains("dest = \"vendor/lib-${version}\""),
+            "{}",
+            saved
+        );
+        assert_eq!(
+            load_config(path).unwrap().sources[0].dest(),
+            "vendor/lib-2.1"
+        );
+
+        fs::write(
+            &file_path,
+            "version = 1\n[[sources]]\ntype = \"sh\"\nname = \"s\"\nscript = \"echo\"\ndest = \"${missing}\"\n",
+        )
+        .unwrap();
+        let err = load_config(path).unwrap_err().to_string();
+        assert!(err.contains("undefined variable 'missing'"), "{}", err);
+
+        // Shell variables in scripts are left alone
+        fs::write(
+            &file_path,
+            "version = 1\n[vars]\nv = \"2\"\n[[sources]]\ntype = \"sh\"\nname = \"s\"\nscript = \"echo ${HOME} ${v}\"\ndest = \"s\"\n",
+        )
+        .unwrap();
+        match &load_config(path).unwrap().sources[0] {
+            Source::Sh { script, .. } => assert_eq!(script, "echo ${HOME} 2"),
+            other => panic!("unexpected source {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_defaults_and_exclude() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("context.toml");
+        fs::write(
+            &file_path,
+            r#"
+version = 1
+exclude = ["**/*.png"]
+
+[vars]
+token = "abc"
+
+[defaults]
+files = ["docs/**"]
+branch = "main"
+max_file_size = "100KB"
+max_total_size = "1MB"
+headers = { Authorization = "Bearer ${token}", Accept = "text/plain" }
+
+[[sources]]
+type = "repo"
+name = "lib"
+repo = "https://github.com/example/lib.git"
+dest = "lib"
+
+[[sources]]
+type = "repo"
+name = "app"
+repo = "https://github.com/example/app.git"
+branch = "develop"
+dest = "app"
+files = ["src/**"]
+max_file_size = 10
+
+[[sources]]
+type = "url"
+name = "spec"
+url = "https://example.com/spec.md"
+dest = "spec.md"
+headers = { Accept = "text/markdown" }
+
+[[sources]]
+type = "sh"
+name = "gen"
+script = "touch a.md b.png"
+dest = "gen"
+"#,
+        )
+        .unwrap();
+
+        let config = load_config(file_path.to_str().unwrap()).unwrap();
+        match &config.sources[0] {
+            Source::Repo { branch, files, .. } => {
+                assert_eq!(branch.as_deref(), Some("main"));
+                assert_eq!(
+                    files.as_deref(),
+                    Some(&["docs/**".to_string(), "!**/*.png".to_string()][..])
+                );
+            }
+            other => panic!("unexpected source {:?}", other),
+        }
+        assert_eq!(
+            config.sources[0].options().limits.max_file_size,
+            Some(100 * 1024)
+        );
+        match &config.sources[1] {
+            Source::Repo { branch, files, .. } => {
+                assert_eq!(branch.as_deref(), Some("develop"));
+                assert_eq!(
+                    files.as_deref(),
+                    Some(&["src/**".to_string(), "!**/*.png".to_string()][..])
+                );
+            }
+            other => panic!("unexpected source {:?}", other),
+        }
+        assert_eq!(config.sources[1].options().limits.max_file_size, Some(10));
+        // A default total size caps each source; there is no global one here
+        assert_eq!(
+            config.sources[1].options().limits.max_total_size,
+            Some(1024 * 1024)
+        );
+        assert_eq!(config.limits.max_total_size, None);
+        match &config.sources[2] {
+            Source::Url { headers, .. } => {
+                assert_eq!(headers.0["Accept"], "text/markdown");
+                assert_eq!(headers.0["Authorization"], "Bearer abc");
+                assert!(!format!("{:?}", headers).contains("abc"));
+            }
+            other => panic!("unexpected source {:?}", other),
+        }
+        // Script output goes through the same rules
+        assert_eq!(
+            config.sources[3].files(),
+            &["docs/**".to_string(), "!**/*.png".to_string()][..]
+        );
+
+        // Saving the raw config keeps the defaults out of the sources
+        let raw = load_config_raw(file_path.to_str().unwrap()).unwrap();
+        save_config(file_path.to_str().unwrap(), &raw).unwrap();
+        let saved = fs::read_to_string(&file_path).unwrap();
+        assert_eq!(saved.matches("branch =").count(), 2);
+    }
+
+    #[test]
+    fn test_enable_and_disable_sources() {
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("context.toml");
+        let path = file_path.to_str().unwrap();
+        let original = "version = 1\n\n# Nightly docs\n[[sources]]\ntype = \"url\"\nname = \"docs\"\nurl = \"https://example.com/docs.md\"\ndest = \"docs.md\"\n";
+        fs::write(&file_path, original).unwrap();
+
+        let mut config = load_config_raw(path).unwrap();
+        assert!(config.set_enabled("docs", false));
+        assert!(!config.set_enabled("missing", false));
+        save_config(path, &config).unwrap();
+        assert!(fs::read_to_string(&file_path)
+            .unwrap()
+            .contains("enabled = false"));
+
+        let mut loaded = load_config(path).unwrap();
+        assert!(!loaded.sources[0].options().is_enabled());
+        let disabled = loaded.take_disabled();
+        assert_eq!(disabled.len(), 1);
+        assert!(loaded.sources.is_empty());
+
+        let mut config = load_config_raw(path).unwrap();
+        config.set_enabled("docs", true);
+        save_config(path, &config).unwrap();
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), original);
+    }
+
+    #[test]
+    fn test_apply_profile() {
+        let mut config: ContextConfig = toml::from_str(
+            r#"
+version = 1
+dest = "ctx"
+
+[profiles.quick]
+tags = ["core"]
+
+[profiles.review]
+sources = ["design"]
+tags = ["core"]
+dest = "ctx-review"
+
+[[sources]]
+type = "url"
+name = "api"
+url = "https://example.com/api.md"
+dest = "api.md"
+tags = ["core"]
+
+[[sources]]
+type = "url"
+name = "design"
+url = "https://example.com/design.md"
+dest = "design.md"
+
+[[sources]]
+type = "url"
+name = "changelog"
+url = "https://example.com/changelog.md"
+dest = "changelog.md"
+"#,
+        )
+        .unwrap();
+
+        let mut quick = ContextConfig {
+            sources: config.sources.clone(),
+            profiles: config.profiles.clone(),
+            dest: config.dest.clone(),
+            ..Default::default()
+        };
+        let skipped = quick.apply_profile("quick").unwrap();
+        assert_eq!(quick.sources.len(), 1);
+        assert_eq!(quick.sources[0].name(), "api");
+        assert_eq!(skipped.len(), 2);
+        assert_eq!(quick.dest.as_deref(), Some("ctx"));
+
+        config.apply_profile("review").unwrap();
+        let names: Vec<&str> = config.sources.iter().map(|s| s.name()).collect();
+        assert_eq!(names, vec!["api", "design"]);
+        assert_eq!(config.dest.as_deref(), Some("ctx-review"));
+
+        assert!(config.apply_profile("missing").is_err());
+        config.profiles.insert(
+            "typo".to_string(),
+            Profile {
+                sources: vec!["desing".to_string()],
+                ..Default::default()
+            },
+        );
+        let err = config.apply_profile("typo").unwrap_err();
+        assert!(err.contains("unknown source 'desing'"), "{}", err);
+    }
+
+    #[test]
+    fn test_resolve_path() {
+        let base = Path::new("/project");
+        assert_eq!(
+            resolve_path(base, "notes/README.md"),
+            PathBuf::from("/project/notes/README.md")
+        );
+        assert_eq!(
+            resolve_path(base, "../shared/docs"),
+            PathBuf::from("/project/../shared/docs")
+        );
+        #[cfg(unix)]
+        assert_eq!(resolve_path(base, "/abs/path"), PathBuf::from("/abs/path"));
+
+        if let Some(home) = std::env::var_os("HOME") {
+            assert_eq!(resolve_path(base, "~"), PathBuf::from(&home));
+            assert_eq!(
+                resolve_path(base, "~/notes"),
+                PathBuf::from(&home).join("notes")
+            );
+        }
+    }
+
     #[test]
     fn test_save_config_error() {
         let config = ContextConfig {
             version: 1,
             dest: Some(".copilot-context".to_string()),
             sources: vec![],
+            ..Default::default()
         };
         // Try to save to a directory path, which should fail
         let result = save_config("/", &config);
@@ -661,7 +1758,7 @@ mod tests {
         fs::write(&file1, "test").unwrap();
 
         // Empty rules should result in no files being kept
-        let rules = parse_file_rules(&[]);
+        let rules = parse_file_rules(&[]).unwrap();
         let results = match_files_and_mark(dir.path(), &rules);
 
         // Should be empty because we return early with empty results
@@ -0,0 +1,167 @@
+use regex::Regex;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::summary::Dropped;
+
+/// Regex filters on file contents, evaluated line by line
+#[derive(Debug, Default)]
+pub struct ContentFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+}
+
+impl ContentFilter {
+    /// Build a filter keeping files with a line matching any `include` pattern
+    /// (if given) and dropping files with a line matching any `exclude` pattern.
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Self, String> {
+        Ok(Self {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Whether text read from `reader` passes the filter.
+    ///
+    /// Lines are streamed, so reading stops as soon as the outcome is known.
+    pub fn matches_reader<R: BufRead>(&self, mut reader: R) -> io::Result<bool> {
+        let mut included = self.include.is_empty();
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            if reader.read_until(b'\n', &mut buf)? == 0 {
+                break;
+            }
+            let line = String::from_utf8_lossy(&buf);
+            if self.exclude.iter().any(|re| re.is_match(&line)) {
+                return Ok(false);
+            }
+            if !included && self.include.iter().any(|re| re.is_match(&line)) {
+                included = true;
+                if self.exclude.is_empty() {
+                    break;
+                }
+            }
+        }
+        Ok(included)
+    }
+
+    /// Whether the file at `path` passes the filter
+    pub fn matches_file(&self, path: &Path) -> io::Result<bool> {
+        if self.is_empty() {
+            return Ok(true);
+        }
+        self.matches_reader(BufReader::new(File::open(path)?))
+    }
+}
+
+fn compile(patterns: &[String]) -> Result<Vec<Regex>, String> {
+    patterns
+        .iter()
+        .map(|p| Regex::new(p).map_err(|e| format!("invalid content pattern '{}': {}", p, e)))
+        .collect()
+}
+
+/// Remove those of `files` that fail `filter`
+pub fn apply_content_filter(
+    files: &[PathBuf],
+    filter: &ContentFilter,
+    verbose: bool,
+) -> Result<Vec<Dropped>, String> {
+    let mut dropped = Vec::new();
+    if filter.is_empty() {
+        return Ok(dropped);
+    }
+
+    for path in files {
+        let keep = filter
+            .matches_file(path)
+            .map_err(|e| format!("failed to read '{}': {}", path.display(), e))?;
+        if !keep {
+            fs::remove_file(path)
+                .map_err(|e| format!("failed to remove file '{}': {}", path.display(), e))?;
+            if verbose {
+                println!(
+                    "copilot-context: removed file by content filter: {}",
+                    path.display()
+                );
+            }
+            dropped.push(Dropped {
+                path: path.clone(),
+                reason: "content filter".to_string(),
+            });
+        }
+    }
+    Ok(dropped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn filter(include: &[&str], exclude: &[&str]) -> ContentFilter {
+        let include: Vec<String> = include.iter().map(|s| s.to_string()).collect();
+        let exclude: Vec<String> = exclude.iter().map(|s| s.to_string()).collect();
+        ContentFilter::new(&include, &exclude).unwrap()
+    }
+
+    #[test]
+    fn test_include_and_exclude() {
+        let f = filter(&["pub trait"], &["@generated"]);
+        assert!(f
+            .matches_reader("use std;\npub trait Foo {}\n".as_bytes())
+            .unwrap());
+        assert!(!f.matches_reader("pub struct Foo;\n".as_bytes()).unwrap());
+        assert!(!f
+            .matches_reader("// @generated\npub trait Foo {}\n".as_bytes())
+            .unwrap());
+        // Exclusion is checked even after an include match
+        assert!(!f
+            .matches_reader("pub trait Foo {}\n// @generated\n".as_bytes())
+            .unwrap());
+    }
+
+    #[test]
+    fn test_empty_filter_keeps_everything() {
+        let f = filter(&[], &[]);
+        assert!(f.is_empty());
+        assert!(f.matches_reader("anything".as_bytes()).unwrap());
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        assert!(ContentFilter::new(&["(unclosed".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn test_apply_content_filter() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("lib.rs"), "pub trait Api {}\n").unwrap();
+        fs::write(
+            dir.path().join("gen.rs"),
+            "// @generated\npub trait Gen {}\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("util.rs"), "fn helper() {}\n").unwrap();
+        // Written by another source with its dest nested inside this one
+        fs::create_dir_all(dir.path().join("vendor")).unwrap();
+        fs::write(dir.path().join("vendor/other.rs"), "fn other() {}\n").unwrap();
+
+        let files = crate::manifest::source_files(dir.path(), ".", &["vendor".to_string()]);
+        let dropped =
+            apply_content_filter(&files, &filter(&["pub trait"], &["@generated"]), false).unwrap();
+
+        assert!(dir.path().join("lib.rs").exists());
+        assert!(!dir.path().join("gen.rs").exists());
+        assert!(!dir.path().join("util.rs").exists());
+        assert!(dir.path().join("vendor/other.rs").exists());
+        assert_eq!(dropped.len(), 2);
+        assert!(dropped.iter().all(|d| d.reason == "content filter"));
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::config::Source;
+
+/// Error if a source depends on a source that doesn't exist or on itself through a
+/// cycle of `depends_on`
+pub fn check_dependencies(sources: &[Source]) -> Result<(), String> {
+    let names: HashMap<&str, &Source> = sources.iter().map(|s| (s.name(), s)).collect();
+    for source in sources {
+        for dependency in &source.options().depends_on {
+            if !names.contains_key(dependency.as_str()) {
+                return Err(format!(
+                    "source '{}' depends on unknown source '{}'",
+                    source.name(),
+                    dependency
+                ));
+            }
+        }
+    }
+
+    // Depth-first search; `path` holds the chain being explored
+    fn visit<'a>(
+        name: &'a str,
+        names: &HashMap<&'a str, &'a Source>,
+        done: &mut BTreeSet<&'a str>,
+        path: &mut Vec<&'a str>,
+    ) -> Result<(), String> {
+        if done.contains(name) {
+            return Ok(());
+        }
+        if let Some(start) = path.iter().position(|n| *n == name) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(name);
+            return Err(format!("dependency cycle: {}", cycle.join(" -> ")));
+        }
+        path.push(name);
+        for dependency in &names[name].options().depends_on {
+            visit(dependency, names, done, path)?;
+        }
+        path.pop();
+        done.insert(name);
+        Ok(())
+    }
+
+    let mut done = BTreeSet::new();
+    for source in sources {
+        visit(source.name(), &names, &mut done, &mut Vec::new())?;
+    }
+    Ok(())
+}
+
+/// Group `sources` into stages that can run in order: every source comes in a later
+/// stage than the sources it depends on, and sources in one stage don't depend on each
+/// other. Sources keep their config order within a stage. Dependencies on sources not
+/// in `sources` (e.g. left out by a profile) are ignored.
+pub fn schedule(sources: Vec<Source>) -> Result<Vec<Vec<Source>>, String> {
+    check_dependencies_within(&sources)?;
+    let mut pending = sources;
+    let mut done: BTreeSet<String> = BTreeSet::new();
+    let mut stages = Vec::new();
+    while !pending.is_empty() {
+        let names: BTreeSet<String> = pending.iter().map(|s| s.name().to_string()).collect();
+        let (ready, waiting): (Vec<Source>, Vec<Source>) =
+            pending.into_iter().partition(|source| {
+                source
+                    .options()
+                    .depends_on
+                    .iter()
+                    .all(|d| done.contains(d) || !names.contains(d))
+            });
+        done.extend(ready.iter().map(|s| s.name().to_string()));
+        stages.push(ready);
+        pending = waiting;
+    }
+    Ok(stages)
+}
+
+/// `check_dependencies` restricted to dependencies among `sources` themselves
+fn check_dependencies_within(sources: &[Source]) -> Result<(), String> {
+    let names: BTreeSet<&str> = sources.iter().map(|s| s.name()).collect();
+    let trimmed: Vec<Source> = sources
+        .iter()
+        .cloned()
+        .map(|mut source| {
+            source
+                .options_mut()
+                .depends_on
+                .retain(|d| names.contains(d.as_str()));
+            source
+        })
+        .collect();
+    check_dependencies(&trimmed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(name: &str, depends_on: &[&str]) -> Source {
+        Source::Sh {
+            name: name.to_string(),
+            script: "true".to_string(),
+            dest: name.to_string(),
+            files: None,
+            options: crate::config::SourceOptions {
+                depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn stage_names(stages: &[Vec<Source>]) -> Vec<Vec<&str>> {
+        stages
+            .iter()
+            .map(|stage| stage.iter().map(|s| s.name()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_schedule_orders_by_dependencies() {
+        let sources = vec![
+            source("index", &["docs", "api"]),
+            source("docs", &[]),
+            source("summary", &["index"]),
+            source("api", &[]),
+        ];
+        let stages = schedule(sources).unwrap();
+        assert_eq!(
+            stage_names(&stages),
+            vec![vec!["docs", "api"], vec!["index"], vec!["summary"]]
+        );
+
+        // A dependency outside the run (e.g. skipped by --only) doesn't hold anything up
+        let stages = schedule(vec![source("index", &["docs"])]).unwrap();
+        assert_eq!(stage_names(&stages), vec![vec!["index"]]);
+    }
+
+    #[test]
+    fn test_dependency_errors() {
+        let err = check_dependencies(&[
+            source("a", &["b"]),
+            source("b", &["c"]),
+            source("c", &["a"]),
+        ])
+        .unwrap_err();
+        assert_eq!(err, "dependency cycle: a -> b -> c -> a");
+
+        let err = check_dependencies(&[source("a", &["a"])]).unwrap_err();
+        assert_eq!(err, "dependency cycle: a -> a");
+
+        let err = check_dependencies(&[source("a", &["nope"])]).unwrap_err();
+        assert!(err.contains("unknown source 'nope'"), "{}", err);
+
+        assert!(schedule(vec![source("a", &["b"]), source("b", &["a"])]).is_err());
+    }
+}
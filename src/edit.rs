@@ -0,0 +1,210 @@
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table, Value};
+
+use crate::limits::parse_size;
+
+/// Rewrite `existing` TOML so it holds the same data as `updated`, keeping comments,
+/// key order and formatting for everything that didn't change. Entries of arrays of
+/// tables (`[[sources]]`, `[[bundles]]`) are matched up by their `name`.
+pub fn update_toml(existing: &str, updated: &str) -> Result<String, toml_edit::TomlError> {
+    let mut doc: DocumentMut = existing.parse()?;
+    let updated: DocumentMut = updated.parse()?;
+    update_table(doc.as_table_mut(), updated.as_table().clone());
+    Ok(doc.to_string())
+}
+
+fn update_table(table: &mut Table, updated: Table) {
+    let stale: Vec<String> = table
+        .iter()
+        .filter(|(key, _)| !updated.contains_key(key))
+        .map(|(key, _)| key.to_string())
+        .collect();
+    for key in stale {
+        table.remove(&key);
+    }
+    for (key, item) in updated {
+        match table.get_mut(&key) {
+            Some(current) => update_item(current, item),
+            None => {
+                table.insert(&key, fresh(item));
+            }
+        }
+    }
+}
+
+fn update_item(current: &mut Item, updated: Item) {
+    match (&mut *current, updated) {
+        (Item::Table(table), Item::Table(updated)) => update_table(table, updated),
+        (Item::ArrayOfTables(array), Item::ArrayOfTables(updated)) => {
+            update_array_of_tables(array, updated)
+        }
+        // Keep inline tables and arrays written inline that way
+        (Item::Value(value), updated) => match updated.into_value() {
+            Ok(updated) if same_value(value, &updated) => {}
+            Ok(mut updated) => {
+                *updated.decor_mut() = value.decor().clone();
+                *value = updated;
+            }
+            Err(updated) => *current = fresh(updated),
+        },
+        (_, updated) => *current = fresh(updated),
+    }
+}
+
+fn update_array_of_tables(current: &mut ArrayOfTables, updated: ArrayOfTables) {
+    let mut previous: Vec<Table> = current.iter().cloned().collect();
+    let mut merged = ArrayOfTables::new();
+    for (i, table) in updated.into_iter().enumerate() {
+        let name = table.get("name").and_then(Item::as_str).map(str::to_string);
+        let position = match &name {
+            Some(name) => previous
+                .iter()
+                .position(|t| t.get("name").and_then(Item::as_str) == Some(name.as_str())),
+            None => (i < previous.len()).then_some(i),
+        };
+        match position {
+            Some(position) => {
+                let mut kept = previous.remove(position);
+                update_table(&mut kept, table);
+                merged.push(kept);
+            }
+            None => merged.push(fresh_table(table)),
+        }
+    }
+    *current = merged;
+}
+
+/// Whether two values mean the same, ignoring formatting. Sizes written as `"10MB"`
+/// are equal to the byte count they were parsed into.
+fn same_value(current: &Value, updated: &Value) -> bool {
+    let parse = |value: &Value| {
+        let mut value = value.clone();
+        value.decor_mut().clear();
+        format!("v = {}", value)
+            .parse::<toml::Table>()
+            .ok()
+            .and_then(|mut t| t.remove("v"))
+    };
+    if let (Value::String(text), Value::Integer(bytes)) = (current, updated) {
+        if parse_size(text.value()).ok() == u64::try_from(*bytes.value()).ok() {
+            return true;
+        }
+    }
+    parse(current).is_some() && parse(current) == parse(updated)
+}
+
+/// Copy of `item` without the document positions it had in the serialized config, so
+/// new tables are placed after the existing ones they belong with
+fn fresh(item: Item) -> Item {
+    match item {
+        Item::Table(table) => Item::Table(fresh_table(table)),
+        Item::ArrayOfTables(array) => {
+            let mut fresh_array = ArrayOfTables::new();
+            for table in array {
+                fresh_array.push(fresh_table(table));
+            }
+            Item::ArrayOfTables(fresh_array)
+        }
+        item => item,
+    }
+}
+
+fn fresh_table(table: Table) -> Table {
+    let mut fresh_table = Table::new();
+    fresh_table.set_implicit(table.is_implicit());
+    for (key, item) in table {
+        fresh_table.insert(&key, fresh(item));
+    }
+    fresh_table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXISTING: &str = r#"# Shared context for the team
+version = 1
+dest = ".copilot-context" # where files go
+max_file_size = "1MB"
+
+# The main API repo
+[[sources]]
+type = "repo"
+name = "api"
+repo = "https://github.com/example/api.git"
+dest = "api"
+files = [ "src/**", "!src/gen/**" ] # no generated code
+
+# Old docs, to be removed
+[[sources]]
+type = "url"
+name = "old"
+url = "https://example.com/old.md"
+dest = "old.md"
+
+[redact]
+enabled = true # scrub tokens
+"#;
+
+    #[test]
+    fn test_update_toml_keeps_comments() {
+        let updated = r#"version = 1
+dest = ".copilot-context"
+max_file_size = 1048576
+
+[[sources]]
+type = "repo"
+name = "api"
+repo = "https://github.com/example/api.git"
+branch = "develop"
+dest = "api"
+files = ["src/**", "!src/gen/**"]
+
+[[sources]]
+type = "path"
+name = "notes"
+path = "notes.md"
+dest = "notes.md"
+
+[redact]
+enabled = true
+"#;
+        let text = update_toml(EXISTING, updated).unwrap();
+        assert_eq!(
+            text,
+            r#"# Shared context for the team
+version = 1
+dest = ".copilot-context" # where files go
+max_file_size = "1MB"
+
+# The main API repo
+[[sources]]
+type = "repo"
+name = "api"
+repo = "https://github.com/example/api.git"
+dest = "api"
+files = [ "src/**", "!src/gen/**" ] # no generated code
+branch = "develop"
+
+[[sources]]
+type = "path"
+name = "notes"
+path = "notes.md"
+dest = "notes.md"
+
+[redact]
+enabled = true # scrub tokens
+"#
+        );
+    }
+
+    #[test]
+    fn test_update_toml_changed_value_keeps_comment() {
+        let updated = EXISTING.replace("dest = \"api\"", "dest = \"vendor/api\"");
+        let updated = updated.replace("\"1MB\"", "2097152");
+        let text = update_toml(EXISTING, &updated).unwrap();
+        assert!(text.contains("dest = \"vendor/api\"\n"), "{}", text);
+        assert!(text.contains("max_file_size = 2097152\n"), "{}", text);
+        assert!(text.contains("# The main API repo\n"), "{}", text);
+        assert!(text.contains("# no generated code"), "{}", text);
+    }
+}
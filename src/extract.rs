@@ -0,0 +1,299 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::FileRule;
+
+/// Part of a file to keep, written after `#` in a `files` rule:
+/// `src/lib.rs#L100-220` or `README.md#heading:Installation`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selector {
+    /// Inclusive, 1-based line range
+    Lines(usize, usize),
+    /// A markdown heading and everything up to the next heading of the same or higher level
+    Heading(String),
+}
+
+impl Selector {
+    /// Parse the text after the last `#` of a rule. `Ok(None)` means it isn't explicit
+    /// selector syntax (`L<n>`, `L<n>-<m>` or `heading:<title>`); the rule is then a
+    /// path such as `docs/C#/**`, or a plain heading title as in `README.md#Usage`.
+    pub fn parse(s: &str) -> Result<Option<Selector>, String> {
+        if let Some(title) = s.strip_prefix("heading:") {
+            let title = title.trim();
+            if title.is_empty() {
+                return Err("empty heading in selector 'heading:'".to_string());
+            }
+            return Ok(Some(Selector::Heading(title.to_string())));
+        }
+        let Some(range) = s.strip_prefix('L') else {
+            return Ok(None);
+        };
+        if !range.starts_with(|c: char| c.is_ascii_digit()) {
+            return Ok(None);
+        }
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        let end = end.strip_prefix('L').unwrap_or(end);
+        match (start.parse::<usize>(), end.parse::<usize>()) {
+            (Ok(start), Ok(end)) if start >= 1 && start <= end => {
+                Ok(Some(Selector::Lines(start, end)))
+            }
+            _ => Err(format!(
+                "invalid line range '{}': expected L<start>-<end> with 1 <= start <= end",
+                s
+            )),
+        }
+    }
+
+    /// The selected part of `text`, or `None` if it doesn't exist in the file
+    pub fn apply(&self, text: &str) -> Option<String> {
+        let lines: Vec<&str> = text.split_inclusive('\n').collect();
+        match self {
+            Selector::Lines(start, end) => {
+                if *start > lines.len() {
+                    return None;
+                }
+                let end = (*end).min(lines.len());
+                Some(lines[start - 1..end].concat())
+            }
+            Selector::Heading(wanted) => {
+                let wanted = slug(wanted);
+                let mut in_fence = false;
+                let mut section: Option<(usize, usize)> = None;
+                for (i, line) in lines.iter().enumerate() {
+                    let trimmed = line.trim_start();
+                    if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+                        in_fence = !in_fence;
+                        continue;
+                    }
+                    if in_fence {
+                        continue;
+                    }
+                    let Some((level, title)) = heading(line) else {
+                        continue;
+                    };
+                    match section {
+                        Some((start, found_level)) if level <= found_level => {
+                            return Some(lines[start..i].concat());
+                        }
+                        None if slug(title) == wanted => section = Some((i, level)),
+                        _ => {}
+                    }
+                }
+                section.map(|(start, _)| lines[start..].concat())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selector::Lines(start, end) if start == end => write!(f, "L{}", start),
+            Selector::Lines(start, end) => write!(f, "L{}-{}", start, end),
+            Selector::Heading(heading) => write!(f, "heading:{}", heading),
+        }
+    }
+}
+
+/// Parse an ATX markdown heading into its level and title
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let line = line.trim_end();
+    let level = line.chars().take_while(|&c| c == '#').count();
+    if level == 0 || level > 6 {
+        return None;
+    }
+    let rest = &line[level..];
+    if !rest.is_empty() && !rest.starts_with(' ') && !rest.starts_with('\t') {
+        return None;
+    }
+    Some((level, rest.trim().trim_end_matches('#').trim_end()))
+}
+
+/// GitHub-style anchor slug, so both `Installation Guide` and `installation-guide` match
+fn slug(s: &str) -> String {
+    s.trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' | '-' => Some('-'),
+            c if c.is_alphanumeric() || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Files under `root` matched by a selector rule, paired with the first matching selector
+pub fn match_selections(root: &Path, rules: &[FileRule]) -> Vec<(PathBuf, Selector)> {
+    let rules = crate::config::resolve_rules(root, rules);
+    let selects: Vec<_> = rules
+        .iter()
+        .filter_map(|r| match r {
+            FileRule::Select(p, s) => Some((p, s)),
+            _ => None,
+        })
+        .collect();
+    if selects.is_empty() {
+        return Vec::new();
+    }
+    crate::config::match_files_and_mark(root, &rules)
+        .into_iter()
+        .filter(|(path, keep)| *keep && path.is_file())
+        .filter_map(|(path, _)| {
+            let rel = crate::config::rule_relative_path(root, &path);
+            selects
+                .iter()
+                .find(|(p, _)| p.matches(&rel))
+                .map(|(_, s)| (path, (*s).clone()))
+        })
+        .collect()
+}
+
+/// Rewrite `path` so it contains only the part chosen by `selector`.
+/// Returns `false` if the selection was not found and the file was left alone.
+pub fn extract_file(path: &Path, selector: &Selector) -> Result<bool, String> {
+    let bytes =
+        fs::read(path).map_err(|e| format!("failed to read '{}': {}", path.display(), e))?;
+    let text =
+        String::from_utf8(bytes).map_err(|_| format!("'{}' is not UTF-8 text", path.display()))?;
+    match selector.apply(&text) {
+        Some(selected) => {
+            fs::write(path, selected)
+                .map_err(|e| format!("failed to write '{}': {}", path.display(), e))?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::parse_file_rules;
+    use tempfile::tempdir;
+
+    const DOC: &str = "\
+# Project
+
+Intro.
+
+## Installation
+
+Run `cargo install`.
+
+```sh
+# not a heading
+```
+
+### From source
+
+Build it.
+
+## Usage
+
+Use it.
+";
+
+    #[test]
+    fn test_parse_selector() {
+        assert_eq!(
+            Selector::parse("L100-220").unwrap(),
+            Some(Selector::Lines(100, 220))
+        );
+        assert_eq!(Selector::parse("L7").unwrap(), Some(Selector::Lines(7, 7)));
+        assert_eq!(
+            Selector::parse("L3-L5").unwrap(),
+            Some(Selector::Lines(3, 5))
+        );
+        assert_eq!(
+            Selector::parse("heading:Installation").unwrap(),
+            Some(Selector::Heading("Installation".to_string()))
+        );
+        // Not selector syntax: the `#` belongs to the path
+        assert_eq!(Selector::parse("/**").unwrap(), None);
+        assert_eq!(Selector::parse("12.md").unwrap(), None);
+        assert_eq!(Selector::parse("Lunch.md").unwrap(), None);
+        // Selector syntax that doesn't parse is an error, not a guess
+        assert!(Selector::parse("L50-10").is_err());
+        assert!(Selector::parse("L0-3").is_err());
+        assert!(Selector::parse("L5-x").is_err());
+        assert!(Selector::parse("heading:").is_err());
+
+        assert_eq!(Selector::Lines(3, 5).to_string(), "L3-5");
+        assert_eq!(Selector::Lines(7, 7).to_string(), "L7");
+        assert_eq!(
+            Selector::Heading("Usage".to_string()).to_string(),
+            "heading:Usage"
+        );
+    }
+
+    #[test]
+    fn test_line_range() {
+        let text = "one\ntwo\nthree\nfour\n";
+        assert_eq!(Selector::Lines(2, 3).apply(text).unwrap(), "two\nthree\n");
+        assert_eq!(Selector::Lines(3, 99).apply(text).unwrap(), "three\nfour\n");
+        assert!(Selector::Lines(5, 6).apply(text).is_none());
+    }
+
+    #[test]
+    fn test_heading_section() {
+        let section = Selector::Heading("Installation".to_string())
+            .apply(DOC)
+            .unwrap();
+        assert!(section.starts_with("## Installation\n"));
+        assert!(section.contains("# not a heading"));
+        assert!(section.contains("### From source"));
+        assert!(!section.contains("## Usage"));
+
+        let last = Selector::Heading("usage".to_string()).apply(DOC).unwrap();
+        assert_eq!(last, "## Usage\n\nUse it.\n");
+
+        let slugged = Selector::Heading("from-source".to_string())
+            .apply(DOC)
+            .unwrap();
+        assert_eq!(slugged, "### From source\n\nBuild it.\n\n");
+
+        assert!(Selector::Heading("Missing".to_string())
+            .apply(DOC)
+            .is_none());
+    }
+
+    #[test]
+    fn test_match_selections_and_extract() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src")).unwrap();
+        fs::write(dir.path().join("src/lib.rs"), "a\nb\nc\nd\n").unwrap();
+        fs::write(dir.path().join("README.md"), DOC).unwrap();
+
+        let rules = parse_file_rules(&[
+            "src/lib.rs#L2-3".to_string(),
+            "README.md#heading:Usage".to_string(),
+        ])
+        .unwrap();
+        let mut selections = match_selections(dir.path(), &rules);
+        selections.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(selections.len(), 2);
+
+        for (path, selector) in &selections {
+            assert!(extract_file(path, selector).unwrap());
+        }
+        assert_eq!(
+            fs::read_to_string(dir.path().join("src/lib.rs")).unwrap(),
+            "b\nc\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("README.md")).unwrap(),
+            "## Usage\n\nUse it.\n"
+        );
+    }
+
+    #[test]
+    fn test_extract_skips_non_utf8_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("latin1.txt");
+        fs::write(&path, b"caf\xe9\n").unwrap();
+        let err = extract_file(&path, &Selector::Lines(1, 1)).unwrap_err();
+        assert!(err.contains("not UTF-8"), "{}", err);
+        assert_eq!(fs::read(&path).unwrap(), b"caf\xe9\n");
+    }
+}
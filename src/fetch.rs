@@ -1,13 +1,24 @@
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::copy;
 use std::path::Path;
 
-/// Downloads a file from the given URL to the destination path.
-pub fn fetch_url(url: &str, dest: &str, verbose: bool) -> Result<(), Box<dyn std::error::Error>> {
+/// Downloads a file from the given URL to the destination path, sending `headers`
+/// with the request.
+pub fn fetch_url(
+    url: &str,
+    dest: &str,
+    headers: &BTreeMap<String, String>,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
     if verbose {
         println!("fetch_url: downloading {} to {}", url, dest);
     }
-    let response = reqwest::blocking::get(url)?;
+    let mut request = reqwest::blocking::Client::new().get(url);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    let response = request.send()?;
     if !response.status().is_success() {
         return Err(format!("Request failed with status: {}", response.status()).into());
     }
@@ -46,7 +57,7 @@ mod tests {
         let dest_path = dir.path().join("testfile.txt");
         let url = format!("{}/testfile.txt", &server_address);
 
-        let result = fetch_url(&url, dest_path.to_str().unwrap(), true);
+        let result = fetch_url(&url, dest_path.to_str().unwrap(), &BTreeMap::new(), true);
         assert!(result.is_ok());
 
         let mut file = fs::File::open(&dest_path).unwrap();
@@ -69,7 +80,7 @@ mod tests {
         let dest_path = dir.path().join("notfound.txt");
         let url = format!("{}/notfound.txt", &server_address);
 
-        let result = fetch_url(&url, dest_path.to_str().unwrap(), false);
+        let result = fetch_url(&url, dest_path.to_str().unwrap(), &BTreeMap::new(), false);
         assert!(result.is_err());
     }
 
@@ -87,7 +98,7 @@ mod tests {
         let nested_path = dir.path().join("a/b/c/file.txt");
         let url = format!("{}/nested/file.txt", &server_address);
 
-        let result = fetch_url(&url, nested_path.to_str().unwrap(), false);
+        let result = fetch_url(&url, nested_path.to_str().unwrap(), &BTreeMap::new(), false);
         assert!(result.is_ok());
 
         let mut file = fs::File::open(&nested_path).unwrap();
@@ -95,4 +106,24 @@ mod tests {
         file.read_to_string(&mut contents).unwrap();
         assert_eq!(contents, "nested content");
     }
+
+    #[test]
+    fn test_fetch_url_sends_headers() {
+        let mut server = Server::new();
+        let _m = server
+            .mock("GET", "/private.txt")
+            .match_header("authorization", "Bearer secret")
+            .with_status(200)
+            .with_body("private")
+            .create();
+
+        let dir = tempdir().unwrap();
+        let dest_path = dir.path().join("private.txt");
+        let url = format!("{}/private.txt", server.url());
+        let headers = BTreeMap::from([("Authorization".to_string(), "Bearer secret".to_string())]);
+
+        fetch_url(&url, dest_path.to_str().unwrap(), &headers, false).unwrap();
+        assert_eq!(fs::read_to_string(&dest_path).unwrap(), "private");
+        assert!(fetch_url(&url, dest_path.to_str().unwrap(), &BTreeMap::new(), false).is_err());
+    }
 }
@@ -0,0 +1,260 @@
+use base64::Engine;
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// How `combine` lays out each file
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Raw content, with optional `--header-format` headers and `--separator`
+    #[default]
+    Plain,
+    /// A heading and a fenced code block per file
+    Markdown,
+    /// `<file path="...">` elements with CDATA content
+    Xml,
+    /// A JSON array of `{"path", "content"}` objects
+    Json,
+}
+
+/// What `combine` does with binary files
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum BinaryOutput {
+    /// Leave them out
+    #[default]
+    Skip,
+    /// Include them as hex, 32 bytes per line
+    Hex,
+    /// Include them as base64, 76 characters per line
+    Base64,
+}
+
+/// Text form of binary `bytes`, or `None` when they should be skipped
+pub fn encode_binary(bytes: &[u8], mode: BinaryOutput) -> Option<String> {
+    let (encoded, width) = match mode {
+        BinaryOutput::Skip => return None,
+        BinaryOutput::Hex => (
+            bytes
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>(),
+            64,
+        ),
+        BinaryOutput::Base64 => (base64::engine::general_purpose::STANDARD.encode(bytes), 76),
+    };
+    let mut out = String::with_capacity(encoded.len() + encoded.len() / width + 1);
+    for line in encoded.as_bytes().chunks(width) {
+        out.push_str(std::str::from_utf8(line).expect("encoded output is ASCII"));
+        out.push('\n');
+    }
+    Some(out)
+}
+
+/// Markdown fence language for a file, from its name or extension
+pub fn fence_language(path: &str) -> &'static str {
+    let path = Path::new(path);
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match name.as_str() {
+        "dockerfile" => return "dockerfile",
+        "makefile" => return "makefile",
+        "cmakelists.txt" => return "cmake",
+        _ => {}
+    }
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "rs" => "rust",
+        "py" => "python",
+        "js" | "mjs" | "cjs" => "javascript",
+        "jsx" => "jsx",
+        "ts" | "mts" | "cts" => "typescript",
+        "tsx" => "tsx",
+        "go" => "go",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "swift" => "swift",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hh" | "hpp" | "hxx" => "cpp",
+        "cs" => "csharp",
+        "rb" => "ruby",
+        "php" => "php",
+        "lua" => "lua",
+        "sh" | "bash" | "zsh" => "bash",
+        "ps1" => "powershell",
+        "sql" => "sql",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "scss" => "scss",
+        "xml" => "xml",
+        "json" => "json",
+        "yaml" | "yml" => "yaml",
+        "toml" => "toml",
+        "ini" | "cfg" => "ini",
+        "md" | "markdown" => "markdown",
+        "proto" => "protobuf",
+        "graphql" | "gql" => "graphql",
+        "diff" | "patch" => "diff",
+        _ => "",
+    }
+}
+
+/// A backtick fence longer than any backtick run in `content`, so the content can't close it
+pub fn code_fence(content: &str) -> String {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in content.chars() {
+        if c == '`' {
+            run += 1;
+            longest = longest.max(run);
+        } else {
+            run = 0;
+        }
+    }
+    "`".repeat((longest + 1).max(3))
+}
+
+/// Escape text for use in a double-quoted XML attribute
+pub fn xml_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Wrap `content` in a CDATA section, splitting any `]]>` it contains across two sections
+pub fn xml_cdata(content: &str) -> String {
+    format!("<![CDATA[{}]]>", content.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+#[derive(Default)]
+struct TreeDir<'a> {
+    dirs: BTreeMap<&'a str, TreeDir<'a>>,
+    files: Vec<(&'a str, &'a str)>,
+}
+
+/// Render `/`-separated paths, each with an annotation, as an ASCII tree
+pub fn render_tree(entries: &[(String, String)]) -> String {
+    let mut root = TreeDir::default();
+    for (path, note) in entries {
+        let mut parts: Vec<&str> = path.split(['/', '\\']).filter(|p| !p.is_empty()).collect();
+        let Some(name) = parts.pop() else {
+            continue;
+        };
+        let mut dir = &mut root;
+        for part in parts {
+            dir = dir.dirs.entry(part).or_default();
+        }
+        dir.files.push((name, note));
+    }
+    let mut out = String::from(".\n");
+    write_tree(&root, "", &mut out);
+    out
+}
+
+fn write_tree(dir: &TreeDir, prefix: &str, out: &mut String) {
+    let mut files = dir.files.clone();
+    files.sort();
+    let count = dir.dirs.len() + files.len();
+    let mut index = 0;
+    for (name, sub) in &dir.dirs {
+        index += 1;
+        let last = index == count;
+        out.push_str(&format!("{}{}{}/\n", prefix, branch(last), name));
+        write_tree(
+            sub,
+            &format!("{}{}", prefix, if last { "    " } else { "│   " }),
+            out,
+        );
+    }
+    for (name, note) in files {
+        index += 1;
+        out.push_str(&format!("{}{}{}", prefix, branch(index == count), name));
+        if !note.is_empty() {
+            out.push_str(&format!(" {}", note));
+        }
+        out.push('\n');
+    }
+}
+
+fn branch(last: bool) -> &'static str {
+    if last {
+        "└── "
+    } else {
+        "├── "
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fence_language() {
+        assert_eq!(fence_language("src/main.rs"), "rust");
+        assert_eq!(fence_language("web/App.TSX"), "tsx");
+        assert_eq!(fence_language("docker/Dockerfile"), "dockerfile");
+        assert_eq!(fence_language("LICENSE"), "");
+    }
+
+    #[test]
+    fn test_code_fence_avoids_collisions() {
+        assert_eq!(code_fence("plain"), "```");
+        assert_eq!(code_fence("```rust\nfn main() {}\n```\n"), "````");
+        assert_eq!(code_fence("`````"), "``````");
+    }
+
+    #[test]
+    fn test_render_tree() {
+        let entries = vec![
+            ("src/main.rs".to_string(), "[2]".to_string()),
+            ("README.md".to_string(), "[1]".to_string()),
+            ("src/util/io.rs".to_string(), String::new()),
+        ];
+        assert_eq!(
+            render_tree(&entries),
+            "\
+.
+├── src/
+│   ├── util/
+│   │   └── io.rs
+│   └── main.rs [2]
+└── README.md [1]
+"
+        );
+    }
+
+    #[test]
+    fn test_encode_binary() {
+        assert_eq!(encode_binary(b"\x00\xff", BinaryOutput::Skip), None);
+        assert_eq!(
+            encode_binary(b"\x00\xff", BinaryOutput::Hex).unwrap(),
+            "00ff\n"
+        );
+        assert_eq!(
+            encode_binary(b"\x00\xff", BinaryOutput::Base64).unwrap(),
+            "AP8=\n"
+        );
+        let wrapped = encode_binary(&[0u8; 40], BinaryOutput::Hex).unwrap();
+        assert_eq!(
+            wrapped.lines().map(str::len).collect::<Vec<_>>(),
+            vec![64, 16]
+        );
+    }
+
+    #[test]
+    fn test_xml_escaping() {
+        assert_eq!(xml_attribute(r#"a&b<"c">"#), "a&amp;b&lt;&quot;c&quot;&gt;");
+        assert_eq!(xml_cdata("x]]>y"), "<![CDATA[x]]]]><![CDATA[>y]]>");
+    }
+}
@@ -1,12 +1,14 @@
 use std::path::Path;
 use std::process::Command;
 
+/// Shallow-clone `repo_url` into `dest` and drop its `.git` directory.
+/// Returns the cloned commit, or `None` when an existing `dest` was reused.
 pub fn fetch_repo(
     repo_url: &str,
     dest: &str,
     branch: Option<&str>,
     verbose: bool,
-) -> Result<(), String> {
+) -> Result<Option<String>, String> {
     if Path::new(dest).exists() {
         if verbose {
             println!("git: destination '{}' already exists, skipping clone", dest);
@@ -19,7 +21,7 @@ pub fn fetch_repo(
                 println!("git: removed .git directory");
             }
         }
-        return Ok(());
+        return Ok(None);
     }
 
     let mut clone_args = vec!["clone", "--depth=1"];
@@ -41,6 +43,14 @@ pub fn fetch_repo(
         return Err(format!("git clone failed for {repo_url}"));
     }
 
+    let rev = Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .current_dir(dest)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string());
+
     // rm .git directory
     let git_dir = Path::new(dest).join(".git");
     if git_dir.exists() {
@@ -51,7 +61,7 @@ pub fn fetch_repo(
         }
     }
 
-    Ok(())
+    Ok(rev)
 }
 
 #[cfg(test)]
@@ -118,7 +128,8 @@ mod tests {
 
         let url = format!("file://{}", repo_dir.to_str().unwrap());
         let res = fetch_repo(&url, dest.to_str().unwrap(), None, false);
-        assert!(res.is_ok());
+        let rev = res.unwrap().expect("fresh clone reports its commit");
+        assert_eq!(rev.len(), 40);
         assert!(dest.exists());
         assert!(dest.join("README.md").exists());
         assert!(!dest.join(".git").exists());
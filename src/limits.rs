@@ -0,0 +1,342 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::summary::Dropped;
+
+/// Number of leading bytes inspected when sniffing for binary content
+const SNIFF_LEN: usize = 8192;
+
+/// What to do with files whose content looks binary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BinaryPolicy {
+    Skip,
+    Keep,
+}
+
+/// Size limits and binary policy, set globally or per source.
+///
+/// Sizes are given in bytes or as strings with a unit, e.g. `"512KB"` or `"10MB"`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Limits {
+    #[serde(
+        default,
+        deserialize_with = "deserialize_size",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<RawSize>")]
+    pub max_file_size: Option<u64>,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_size",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schemars(with = "Option<RawSize>")]
+    pub max_total_size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub binary: Option<BinaryPolicy>,
+}
+
+impl Limits {
+    /// Per-source limits, falling back to the global file size limit and binary policy.
+    /// The global `max_total_size` caps the whole context folder and is not inherited;
+    /// a per-source default for it comes from `[defaults]`, filled in when loading.
+    pub fn with_fallback(&self, global: &Limits) -> Limits {
+        Limits {
+            max_file_size: self.max_file_size.or(global.max_file_size),
+            max_total_size: self.max_total_size,
+            binary: self.binary.or(global.binary),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.max_file_size.is_none() && self.max_total_size.is_none() && self.binary.is_none()
+    }
+}
+
+/// Parse a size such as `1024`, `"512KB"`, `"10 MiB"` or `"2g"` into bytes
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", s))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024 * 1024,
+        "g" | "gb" | "gib" => 1024 * 1024 * 1024,
+        other => return Err(format!("unknown size unit '{}' in '{}'", other, s)),
+    };
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("size '{}' is too large", s))
+}
+
+/// Format a byte count for display: `512 B`, `1.5 KB`, `10.0 MB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 3] = ["KB", "MB", "GB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+/// A size as written in the config: a byte count or a string like `"10MB"`
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum RawSize {
+    Bytes(u64),
+    Text(String),
+}
+
+fn deserialize_size<'de, D>(deserializer: D) -> Result<Option<u64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<RawSize>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(RawSize::Bytes(n)) => Ok(Some(n)),
+        Some(RawSize::Text(s)) => parse_size(&s).map(Some).map_err(serde::de::Error::custom),
+    }
+}
+
+/// Heuristic binary detection: NUL bytes or a high share of control characters
+pub fn looks_binary(bytes: &[u8]) -> bool {
+    if bytes.is_empty() {
+        return false;
+    }
+    if bytes.contains(&0) {
+        return true;
+    }
+    let control = bytes
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b))
+        .count();
+    control * 10 > bytes.len()
+}
+
+/// Sniff the start of the file at `path` for binary content
+pub fn is_binary_file(path: &Path) -> std::io::Result<bool> {
+    let mut buf = Vec::with_capacity(SNIFF_LEN);
+    File::open(path)?
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut buf)?;
+    Ok(looks_binary(&buf))
+}
+
+/// Remove those of `files` that violate `limits`.
+///
+/// Files are visited in the order given, so once `max_total_size` is reached the
+/// remaining files are dropped.
+pub fn enforce_limits(
+    files: &[PathBuf],
+    limits: &Limits,
+    verbose: bool,
+) -> Result<Vec<Dropped>, String> {
+    enforce_limits_after(files, 0, limits, verbose)
+}
+
+/// Like `enforce_limits`, with `reserved` bytes of files that must stay already
+/// counted toward `max_total_size`
+pub fn enforce_limits_after(
+    files: &[PathBuf],
+    reserved: u64,
+    limits: &Limits,
+    verbose: bool,
+) -> Result<Vec<Dropped>, String> {
+    let mut dropped = Vec::new();
+    if limits.is_empty() {
+        return Ok(dropped);
+    }
+
+    let mut total: u64 = reserved;
+    for path in files {
+        let size = fs::metadata(path)
+            .map_err(|e| format!("failed to get metadata for '{}': {}", path.display(), e))?
+            .len();
+
+        let reason = if limits.max_file_size.is_some_and(|max| size > max) {
+            Some(format!(
+                "exceeds max_file_size ({} > {} bytes)",
+                size,
+                limits.max_file_size.unwrap()
+            ))
+        } else if limits.binary == Some(BinaryPolicy::Skip)
+            && is_binary_file(path)
+                .map_err(|e| format!("failed to read '{}': {}", path.display(), e))?
+        {
+            Some("binary content".to_string())
+        } else if limits.max_total_size.is_some_and(|max| total + size > max) {
+            Some(format!(
+                "exceeds max_total_size ({} bytes)",
+                limits.max_total_size.unwrap()
+            ))
+        } else {
+            total += size;
+            None
+        };
+
+        if let Some(reason) = reason {
+            fs::remove_file(path)
+                .map_err(|e| format!("failed to remove file '{}': {}", path.display(), e))?;
+            if verbose {
+                println!("copilot-context: dropped {}: {}", path.display(), reason);
+            }
+            dropped.push(Dropped {
+                path: path.clone(),
+                reason,
+            });
+        }
+    }
+    Ok(dropped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1024").unwrap(), 1024);
+        assert_eq!(parse_size("512KB").unwrap(), 512 * 1024);
+        assert_eq!(parse_size("10 MiB").unwrap(), 10 * 1024 * 1024);
+        assert_eq!(parse_size("2g").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert!(parse_size("ten MB").is_err());
+        assert!(parse_size("10 parsecs").is_err());
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(10 * 1024 * 1024), "10.0 MB");
+    }
+
+    #[test]
+    fn test_limits_deserialize_numbers_and_strings() {
+        let limits: Limits =
+            toml::from_str("max_file_size = \"1MB\"\nmax_total_size = 2048\nbinary = \"skip\"")
+                .unwrap();
+        assert_eq!(limits.max_file_size, Some(1024 * 1024));
+        assert_eq!(limits.max_total_size, Some(2048));
+        assert_eq!(limits.binary, Some(BinaryPolicy::Skip));
+    }
+
+    #[test]
+    fn test_with_fallback() {
+        let global = Limits {
+            max_file_size: Some(100),
+            max_total_size: Some(1000),
+            binary: Some(BinaryPolicy::Skip),
+        };
+        let source = Limits {
+            binary: Some(BinaryPolicy::Keep),
+            ..Default::default()
+        };
+        let merged = source.with_fallback(&global);
+        assert_eq!(merged.max_file_size, Some(100));
+        assert_eq!(merged.max_total_size, None);
+        assert_eq!(merged.binary, Some(BinaryPolicy::Keep));
+    }
+
+    #[test]
+    fn test_looks_binary() {
+        assert!(!looks_binary(b"fn main() {}\n"));
+        assert!(!looks_binary("caf\u{e9} na\u{ef}ve".as_bytes()));
+        assert!(looks_binary(b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR"));
+        assert!(looks_binary(&[1, 2, 3, 4, 5, 6, 7, 8]));
+    }
+
+    #[test]
+    fn test_enforce_limits() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "aaaa").unwrap();
+        fs::write(dir.path().join("b.bin"), b"\x00\x01\x02").unwrap();
+        fs::write(dir.path().join("c.txt"), "cccccccccc").unwrap();
+        fs::write(dir.path().join("d.txt"), "dddd").unwrap();
+        fs::write(dir.path().join("e.txt"), "eeee").unwrap();
+
+        let limits = Limits {
+            max_file_size: Some(8),
+            max_total_size: Some(10),
+            binary: Some(BinaryPolicy::Skip),
+        };
+        let files = crate::manifest::source_files(dir.path(), ".", &[]);
+        let dropped = enforce_limits(&files, &limits, false).unwrap();
+
+        assert!(dir.path().join("a.txt").exists());
+        assert!(dir.path().join("d.txt").exists());
+        assert!(!dir.path().join("b.bin").exists());
+        assert!(!dir.path().join("c.txt").exists());
+        assert!(!dir.path().join("e.txt").exists());
+
+        let reasons: Vec<_> = dropped.iter().map(|d| d.reason.as_str()).collect();
+        assert_eq!(reasons.len(), 3);
+        assert_eq!(reasons[0], "binary content");
+        assert!(reasons[1].starts_with("exceeds max_file_size"));
+        assert!(reasons[2].starts_with("exceeds max_total_size"));
+    }
+
+    #[test]
+    fn test_enforce_limits_leaves_nested_source_alone() {
+        let dir = tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("docs/api")).unwrap();
+        fs::write(dir.path().join("docs/guide.md"), "guide").unwrap();
+        fs::write(dir.path().join("docs/api/big.md"), "a large page").unwrap();
+
+        // `docs` caps file size; `docs/api` belongs to another source without limits
+        let limits = Limits {
+            max_file_size: Some(8),
+            ..Default::default()
+        };
+        let files = crate::manifest::source_files(dir.path(), "docs", &["docs/api".to_string()]);
+        assert!(enforce_limits(&files, &limits, false).unwrap().is_empty());
+        assert!(dir.path().join("docs/api/big.md").exists());
+
+        let files = crate::manifest::source_files(dir.path(), "docs", &[]);
+        assert_eq!(enforce_limits(&files, &limits, false).unwrap().len(), 1);
+        assert!(!dir.path().join("docs/api/big.md").exists());
+    }
+
+    #[test]
+    fn test_enforce_limits_after_reserved() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), "aaaa").unwrap();
+        fs::write(dir.path().join("b.txt"), "bbbb").unwrap();
+
+        // 6 bytes of files that must stay leave room for one more 4-byte file
+        let limits = Limits {
+            max_total_size: Some(10),
+            ..Default::default()
+        };
+        let files = crate::manifest::source_files(dir.path(), ".", &[]);
+        let dropped = enforce_limits_after(&files, 6, &limits, false).unwrap();
+        assert_eq!(dropped.len(), 1);
+        assert!(dir.path().join("a.txt").exists());
+        assert!(!dir.path().join("b.txt").exists());
+    }
+
+    #[test]
+    fn test_enforce_limits_keeps_binary_by_default() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("sprite.png");
+        fs::write(&file, b"\x89PNG\r\n\x1a\n\x00\x00").unwrap();
+
+        let files = crate::manifest::source_files(dir.path(), "sprite.png", &[]);
+        let dropped = enforce_limits(&files, &Limits::default(), false).unwrap();
+        assert!(dropped.is_empty());
+        assert!(file.exists());
+    }
+}
@@ -0,0 +1,424 @@
+use crate::config::{ContextConfig, Source};
+use crate::limits::format_size;
+use crate::manifest::{manifest_key, source_files, Manifest, SourceRecord};
+use clap::ValueEnum;
+use serde::Serialize;
+
+/// How `list` prints the sources
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ListFormat {
+    /// An aligned table, one source per row
+    #[default]
+    Table,
+    /// An array of objects, one per source
+    Json,
+}
+
+/// What `list` shows about one source
+#[derive(Debug, Serialize)]
+pub struct SourceRow {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    /// Repo URL, URL or path; `None` for `sh` sources
+    pub origin: Option<String>,
+    pub dest: String,
+    /// Number of file rules, including `[defaults]` and the global `exclude`
+    pub rules: usize,
+    /// Unix time of the last successful sync
+    pub last_sync: Option<u64>,
+    pub status: &'static str,
+    /// Bytes on disk under `dest`, leaving out other sources' nested dests
+    pub size: u64,
+    /// Config file the source is defined in
+    pub from: String,
+}
+
+/// State of a source in the context folder: `disabled`, `never synced`, `failed`,
+/// `missing` (synced, but none of its `files` are left) or `ok`
+pub fn status(
+    source: &Source,
+    record: Option<&SourceRecord>,
+    files: &[(String, u64)],
+) -> &'static str {
+    if !source.options().is_enabled() {
+        return "disabled";
+    }
+    match record {
+        None => "never synced",
+        Some(record) if record.failed => "failed",
+        Some(_) if files.is_empty() => "missing",
+        Some(_) => "ok",
+    }
+}
+
+/// Files `source` produced in the context folder with their sizes, keyed like the
+/// manifest. Files under the dest of another source in `config` belong to that one.
+pub fn produced_files(config: &ContextConfig, source: &Source) -> Vec<(String, u64)> {
+    let context_dir = config.context_dir();
+    let dests: Vec<String> = config
+        .sources
+        .iter()
+        .map(|s| s.dest().to_string())
+        .collect();
+    source_files(&context_dir, source.dest(), &dests)
+        .into_iter()
+        .map(|path| {
+            let size = path.metadata().map(|m| m.len()).unwrap_or(0);
+            (manifest_key(&context_dir, &path), size)
+        })
+        .collect()
+}
+
+pub fn source_rows(
+    config: &ContextConfig,
+    manifest: &Manifest,
+    config_path: &str,
+) -> Vec<SourceRow> {
+    config
+        .sources
+        .iter()
+        .map(|source| {
+            let record = manifest.sources.get(source.name());
+            let files = produced_files(config, source);
+            SourceRow {
+                name: source.name().to_string(),
+                kind: source.kind(),
+                origin: source.origin().map(str::to_string),
+                dest: source.dest().to_string(),
+                rules: source.files().len(),
+                last_sync: record.and_then(|r| r.synced_at),
+                status: status(source, record, &files),
+                size: files.iter().map(|(_, size)| size).sum(),
+                from: config
+                    .origins
+                    .get(source.name())
+                    .cloned()
+                    .unwrap_or_else(|| config_path.to_string()),
+            }
+        })
+        .collect()
+}
+
+/// How long ago `synced_at` was, e.g. `5m ago`, or `never`
+pub fn format_age(synced_at: Option<u64>, now: u64) -> String {
+    let Some(synced_at) = synced_at else {
+        return "never".to_string();
+    };
+    let seconds = now.saturating_sub(synced_at);
+    match seconds {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        3600..=86399 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+/// Rows as an aligned table. The FROM column is only shown when some source comes
+/// from an included file.
+pub fn render_table(rows: &[SourceRow], config_path: &str, now: u64) -> String {
+    let with_from = rows.iter().any(|row| row.from != config_path);
+    let mut header = vec![
+        "NAME",
+        "TYPE",
+        "ORIGIN",
+        "DEST",
+        "RULES",
+        "LAST SYNC",
+        "STATUS",
+        "SIZE",
+    ];
+    if with_from {
+        header.push("FROM");
+    }
+    let mut table: Vec<Vec<String>> = vec![header.iter().map(|h| h.to_string()).collect()];
+    for row in rows {
+        let mut cells = vec![
+            row.name.clone(),
+            row.kind.to_string(),
+            row.origin.clone().unwrap_or_else(|| "-".to_string()),
+            row.dest.clone(),
+            row.rules.to_string(),
+            format_age(row.last_sync, now),
+            row.status.to_string(),
+            format_size(row.size),
+        ];
+        if with_from {
+            cells.push(row.from.clone());
+        }
+        table.push(cells);
+    }
+    let widths: Vec<usize> = (0..table[0].len())
+        .map(|i| table.iter().map(|cells| cells[i].len()).max().unwrap_or(0))
+        .collect();
+    let mut out = String::new();
+    for cells in &table {
+        let line: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+pub fn render_json(rows: &[SourceRow]) -> Result<String, String> {
+    serde_json::to_string_pretty(rows).map_err(|e| format!("failed to serialize sources: {}", e))
+}
+
+/// Full effective configuration of `source`, with header values masked, followed by
+/// its sync state and the files it produced
+pub fn render_show(
+    source: &Source,
+    config: &ContextConfig,
+    manifest: &Manifest,
+    config_path: &str,
+    now: u64,
+) -> Result<String, String> {
+    let mut shown = source.clone();
+    if let Source::Url { headers, .. } = &mut shown {
+        for value in headers.0.values_mut() {
+            *value = "***".to_string();
+        }
+    }
+    let settings = toml::to_string_pretty(&shown)
+        .map_err(|e| format!("failed to serialize source '{}': {}", source.name(), e))?;
+    let record = manifest.sources.get(source.name());
+    let files = produced_files(config, source);
+    let from = config
+        .origins
+        .get(source.name())
+        .map(String::as_str)
+        .unwrap_or(config_path);
+
+    let mut out = format!("# {} (from {})\n{}\n", source.name(), from, settings);
+    out.push_str(&format!("Status: {}\n", status(source, record, &files)));
+    out.push_str(&format!(
+        "Last sync: {}\n",
+        format_age(record.and_then(|r| r.synced_at), now)
+    ));
+    if let Some(rev) = record.and_then(|r| r.rev.as_deref()) {
+        out.push_str(&format!("Revision: {}\n", rev));
+    }
+    if files.is_empty() {
+        out.push_str("Files: none\n");
+        return Ok(out);
+    }
+    let total: u64 = files.iter().map(|(_, size)| size).sum();
+    out.push_str(&format!(
+        "Files ({}, {}):\n",
+        files.len(),
+        format_size(total)
+    ));
+    for (path, size) in &files {
+        out.push_str(&format!("  {} ({})", path, format_size(*size)));
+        if let Some(selector) = record.and_then(|r| r.partial.get(path)) {
+            out.push_str(&format!(" [{}]", selector));
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SourceOptions;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn config(base_dir: &Path) -> ContextConfig {
+        let path = |name: &str, options: SourceOptions| Source::Path {
+            name: name.to_string(),
+            path: format!("../{}", name),
+            dest: name.to_string(),
+            files: Some(vec!["**/*.md".to_string(), "!drafts/**".to_string()]),
+            options,
+        };
+        ContextConfig {
+            dest: Some("ctx".to_string()),
+            base_dir: base_dir.to_path_buf(),
+            sources: vec![
+                path("docs", SourceOptions::default()),
+                path("notes", SourceOptions::default()),
+                path(
+                    "old",
+                    SourceOptions {
+                        enabled: Some(false),
+                        ..Default::default()
+                    },
+                ),
+                Source::Sh {
+                    name: "gen".to_string(),
+                    script: "echo hi > gen.txt".to_string(),
+                    dest: "gen".to_string(),
+                    files: None,
+                    options: SourceOptions::default(),
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn manifest() -> Manifest {
+        let mut manifest = Manifest::default();
+        let record = |dest: &str, failed: bool| SourceRecord {
+            dest: dest.to_string(),
+            synced_at: Some(1_000),
+            failed,
+            ..Default::default()
+        };
+        manifest
+            .sources
+            .insert("docs".to_string(), record("docs", false));
+        manifest
+            .sources
+            .insert("notes".to_string(), record("notes", false));
+        manifest
+            .sources
+            .insert("gen".to_string(), record("gen", true));
+        manifest
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(None, 100), "never");
+        assert_eq!(format_age(Some(100), 130), "just now");
+        assert_eq!(format_age(Some(100), 400), "5m ago");
+        assert_eq!(format_age(Some(0), 7200), "2h ago");
+        assert_eq!(format_age(Some(0), 3 * 86400), "3d ago");
+    }
+
+    #[test]
+    fn test_source_rows() {
+        let dir = tempdir().unwrap();
+        let docs = dir.path().join("ctx/docs");
+        std::fs::create_dir_all(&docs).unwrap();
+        std::fs::write(docs.join("a.md"), "hello").unwrap();
+        std::fs::write(docs.join("b.md"), "world!").unwrap();
+        let config = config(dir.path());
+
+        let rows = source_rows(&config, &manifest(), "context.toml");
+        let summary: Vec<(&str, &str, usize, u64)> = rows
+            .iter()
+            .map(|r| (r.name.as_str(), r.status, r.rules, r.size))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("docs", "ok", 2, 11),
+                ("notes", "missing", 2, 0),
+                ("old", "disabled", 2, 0),
+                ("gen", "failed", 0, 0),
+            ]
+        );
+
+        let table = render_table(&rows, "context.toml", 1_300);
+        let lines: Vec<&str> = table.lines().collect();
+        assert!(lines[0].starts_with("NAME   TYPE  ORIGIN"), "{}", table);
+        assert!(!lines[0].contains("FROM"), "{}", table);
+        assert!(
+            lines[1].contains("docs   path  ../docs") && lines[1].contains("5m ago"),
+            "{}",
+            table
+        );
+        assert!(lines[4].contains("gen    sh    -"), "{}", table);
+
+        let json: serde_json::Value = serde_json::from_str(&render_json(&rows).unwrap()).unwrap();
+        assert_eq!(json[0]["type"], "path");
+        assert_eq!(json[0]["last_sync"], 1_000);
+        assert_eq!(json[3]["origin"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_dot_dest_leaves_out_other_sources() {
+        let dir = tempdir().unwrap();
+        let ctx = dir.path().join("ctx");
+        std::fs::create_dir_all(ctx.join("docs")).unwrap();
+        std::fs::write(ctx.join("docs/a.md"), "hello").unwrap();
+        std::fs::write(ctx.join(crate::manifest::MANIFEST_FILE), "{}").unwrap();
+        let mut config = config(dir.path());
+        config.sources.push(Source::Sh {
+            name: "root".to_string(),
+            script: "echo hi > root.txt".to_string(),
+            dest: ".".to_string(),
+            files: None,
+            options: SourceOptions::default(),
+        });
+        let root = config.sources.last().unwrap().clone();
+        let mut manifest = manifest();
+        manifest.sources.insert(
+            "root".to_string(),
+            SourceRecord {
+                dest: ".".to_string(),
+                synced_at: Some(1_000),
+                ..Default::default()
+            },
+        );
+
+        // Only other sources' files are there
+        let rows = source_rows(&config, &manifest, "context.toml");
+        assert_eq!((rows[4].status, rows[4].size), ("missing", 0));
+        assert_eq!((rows[0].status, rows[0].size), ("ok", 5));
+
+        std::fs::write(ctx.join("root.txt"), "hi\n").unwrap();
+        let rows = source_rows(&config, &manifest, "context.toml");
+        assert_eq!((rows[4].status, rows[4].size), ("ok", 3));
+        let shown = render_show(&root, &config, &manifest, "context.toml", 1_000).unwrap();
+        assert!(
+            shown.contains("Files (1, 3 B):\n  root.txt (3 B)\n"),
+            "{}",
+            shown
+        );
+    }
+
+    #[test]
+    fn test_render_show() {
+        let dir = tempdir().unwrap();
+        let docs = dir.path().join("ctx/docs");
+        std::fs::create_dir_all(&docs).unwrap();
+        std::fs::write(docs.join("a.md"), "hello").unwrap();
+        let config = config(dir.path());
+        let mut manifest = manifest();
+        manifest
+            .sources
+            .get_mut("docs")
+            .unwrap()
+            .partial
+            .insert("docs/a.md".to_string(), "L1-5".to_string());
+
+        let shown = render_show(
+            &config.sources[0],
+            &config,
+            &manifest,
+            "context.toml",
+            1_000,
+        )
+        .unwrap();
+        assert!(
+            shown.starts_with("# docs (from context.toml)\n"),
+            "{}",
+            shown
+        );
+        assert!(shown.contains("type = \"path\""), "{}", shown);
+        assert!(shown.contains("Status: ok\n"), "{}", shown);
+        assert!(shown.contains("  docs/a.md (5 B) [L1-5]\n"), "{}", shown);
+
+        let url = Source::Url {
+            name: "spec".to_string(),
+            url: "https://example.com/spec".to_string(),
+            dest: "spec".to_string(),
+            files: None,
+            headers: crate::config::Headers(
+                [("Authorization".to_string(), "Bearer secret".to_string())].into(),
+            ),
+            options: SourceOptions::default(),
+        };
+        let shown = render_show(&url, &config, &manifest, "context.toml", 1_000).unwrap();
+        assert!(!shown.contains("secret"), "{}", shown);
+        assert!(shown.contains("Status: never synced\n"), "{}", shown);
+        assert!(shown.contains("Files: none\n"), "{}", shown);
+    }
+}
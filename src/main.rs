@@ -1,21 +1,49 @@
 use clap::{Parser, Subcommand};
+use std::collections::{BTreeMap, BTreeSet};
 
+mod chunk;
 mod clean;
 mod combine;
 mod config;
+mod content;
 mod copy;
+mod deps;
+mod edit;
+mod extract;
 mod fetch;
+mod format;
 mod git;
+mod include;
+mod limits;
+mod listing;
+mod manifest;
+mod migrate;
+mod order;
+mod redact;
+mod select;
 mod sh;
+mod summary;
+mod tokens;
+mod vars;
 
 use combine::CombineArgs;
 use config::{match_files_and_mark, parse_file_rules};
+use select::SourceSelection;
 
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// List all sources
     #[clap(about = "List all sources in the context configuration")]
-    List,
+    List {
+        #[clap(long, value_enum, default_value = "table", help = "Output format")]
+        format: listing::ListFormat,
+    },
+    /// Show one source in detail
+    #[clap(about = "Show a source's effective configuration and the files it produced")]
+    Show {
+        #[clap(help = "Name of the source to show")]
+        name: String,
+    },
     /// Add a new source
     #[clap(about = "Add a new source to the context configuration")]
     Add {
@@ -64,17 +92,51 @@ enum Commands {
         #[clap(long, help = "New shell script to run (for kind=sh)")]
         script: Option<String>,
     },
+    /// Enable a disabled source
+    #[clap(about = "Enable a source that was disabled")]
+    Enable {
+        #[clap(long, help = "Name of the source to enable")]
+        name: String,
+    },
+    /// Disable a source without removing it
+    #[clap(about = "Disable a source so sync skips it, keeping its settings")]
+    Disable {
+        #[clap(long, help = "Name of the source to disable")]
+        name: String,
+    },
     /// Initialize a new context.toml file
     #[clap(about = "Generate a default context.toml if one does not exist")]
     Init,
     /// Clean the context folder, removing files not specified in the configuration
     #[clap(about = "Clean the context folder, removing files not specified in the configuration")]
-    Clean,
+    Clean {
+        #[clap(flatten)]
+        selection: SourceSelection,
+        #[clap(
+            long,
+            help = "Also remove files written by disabled sources (by default they are kept)"
+        )]
+        purge_disabled: bool,
+    },
+    /// Fetch and refresh the sources (the default when no command is given)
+    #[clap(about = "Sync the sources into the context folder")]
+    Sync(SourceSelection),
     /// Combine files from the context directory
     #[clap(
         about = "Combine files from the context directory into a single output or the clipboard"
     )]
     Combine(CombineArgs),
+    /// Upgrade context.toml to the current config version
+    #[clap(about = "Upgrade context.toml to the current config version, showing a diff first")]
+    Migrate {
+        #[clap(long, help = "Only show the diff, don't write anything")]
+        dry_run: bool,
+        #[clap(long, short, help = "Apply the changes without asking")]
+        yes: bool,
+    },
+    /// Print the JSON Schema for context.toml
+    #[clap(about = "Print the JSON Schema for context.toml, for editor validation")]
+    Schema,
 }
 
 #[derive(Parser, Debug)]
@@ -93,6 +155,10 @@ struct Cli {
     #[clap(short, long)]
     verbose: bool,
 
+    /// Only use the sources selected by this `[profiles.<name>]` section
+    #[clap(long)]
+    profile: Option<String>,
+
     #[clap(subcommand)]
     command: Option<Commands>,
 }
@@ -101,7 +167,19 @@ fn main() {
     let cli = Cli::parse();
     if let Some(cmd) = &cli.command {
         use config::{
-            load_config, make_source, save_config, write_default_config_if_missing, SourceUpdate,
+            load_config, load_config_raw, make_source, save_config,
+            write_default_config_if_missing, SourceUpdate,
+        };
+        // Sources that only exist in an included file can't be edited from here
+        let not_found = |name: &str| match load_config(&cli.config)
+            .ok()
+            .and_then(|c| c.origins.get(name).cloned())
+        {
+            Some(origin) => println!(
+                "Source '{}' is included from {}; edit it there.",
+                name, origin
+            ),
+            None => println!("No source found with name: {}", name),
         };
         match cmd {
             Commands::Init => {
@@ -112,10 +190,61 @@ fn main() {
                 }
                 return;
             }
-            Commands::List => {
-                let config = load_config(&cli.config).expect("Failed to load config");
-                for src in &config.sources {
-                    println!("{:?}", src);
+            Commands::List { format } => {
+                let (config, _) = match load_profile(&cli) {
+                    Ok(loaded) => loaded,
+                    Err(e) => {
+                        eprintln!("Error loading {}: {}", cli.config, e);
+                        return;
+                    }
+                };
+                let manifest = match manifest::Manifest::load(&config.context_dir()) {
+                    Ok(manifest) => manifest,
+                    Err(e) => {
+                        eprintln!(
+                            "Error reading sync state: {} (the next sync rewrites it)",
+                            e
+                        );
+                        return;
+                    }
+                };
+                let rows = listing::source_rows(&config, &manifest, &cli.config);
+                match format {
+                    listing::ListFormat::Table => {
+                        print!("{}", listing::render_table(&rows, &cli.config, unix_now()))
+                    }
+                    listing::ListFormat::Json => println!(
+                        "{}",
+                        listing::render_json(&rows).expect("Failed to list sources")
+                    ),
+                }
+                return;
+            }
+            Commands::Show { name } => {
+                let (config, _) = match load_profile(&cli) {
+                    Ok(loaded) => loaded,
+                    Err(e) => {
+                        eprintln!("Error loading {}: {}", cli.config, e);
+                        return;
+                    }
+                };
+                let manifest = match manifest::Manifest::load(&config.context_dir()) {
+                    Ok(manifest) => manifest,
+                    Err(e) => {
+                        eprintln!(
+                            "Error reading sync state: {} (the next sync rewrites it)",
+                            e
+                        );
+                        return;
+                    }
+                };
+                match config.sources.iter().find(|s| s.name() == name) {
+                    Some(source) => print!(
+                        "{}",
+                        listing::render_show(source, &config, &manifest, &cli.config, unix_now())
+                            .expect("Failed to show source")
+                    ),
+                    None => println!("No source found with name: {}", name),
                 }
                 return;
             }
@@ -130,7 +259,7 @@ fn main() {
                 files,
                 script,
             } => {
-                let mut config = load_config(&cli.config).expect("Failed to load config");
+                let mut config = load_config_raw(&cli.config).expect("Failed to load config");
                 let new_source = make_source(
                     kind,
                     name.clone(),
@@ -148,12 +277,12 @@ fn main() {
                 return;
             }
             Commands::Remove { name } => {
-                let mut config = load_config(&cli.config).expect("Failed to load config");
+                let mut config = load_config_raw(&cli.config).expect("Failed to load config");
                 if config.remove_source(name) {
                     save_config(&cli.config, &config).expect("Failed to save config");
                     println!("Source removed.");
                 } else {
-                    println!("No source found with name: {}", name);
+                    not_found(name);
                 }
                 return;
             }
@@ -167,7 +296,7 @@ fn main() {
                 files,
                 script,
             } => {
-                let mut config = load_config(&cli.config).expect("Failed to load config");
+                let mut config = load_config_raw(&cli.config).expect("Failed to load config");
                 let update = SourceUpdate::from_args(
                     repo.clone(),
                     url.clone(),
@@ -181,65 +310,184 @@ fn main() {
                     save_config(&cli.config, &config).expect("Failed to save config");
                     println!("Source updated.");
                 } else {
-                    println!("No source found with name: {}", name);
+                    not_found(name);
+                }
+                return;
+            }
+            Commands::Enable { name } | Commands::Disable { name } => {
+                let enabled = matches!(cmd, Commands::Enable { .. });
+                let mut config = load_config_raw(&cli.config).expect("Failed to load config");
+                if config.set_enabled(name, enabled) {
+                    save_config(&cli.config, &config).expect("Failed to save config");
+                    println!("Source {}.", if enabled { "enabled" } else { "disabled" });
+                } else {
+                    not_found(name);
                 }
                 return;
             }
-            Commands::Clean => {
-                let config = load_config(&cli.config).expect("Failed to load config");
-                let dest_string = config
-                    .dest
-                    .clone()
-                    .unwrap_or_else(|| ".copilot-context".to_string());
+            Commands::Clean {
+                selection,
+                purge_disabled,
+            } => {
+                let (config, mut skipped) = match load_selection(&cli, selection) {
+                    Ok(loaded) => loaded,
+                    Err(e) => {
+                        eprintln!("Error loading {}: {}", cli.config, e);
+                        return;
+                    }
+                };
+                // Disabled sources' files are kept like unselected ones unless purged
+                if *purge_disabled {
+                    skipped.retain(|s| s.options().is_enabled());
+                }
+                let dest_string = config.context_dir().to_string_lossy().into_owned();
 
-                if let Err(e) =
-                    clean::clean_context_folder(&dest_string, &config.sources, cli.verbose)
-                {
+                if let Err(e) = clean::clean_context_folder(
+                    &dest_string,
+                    &config.sources,
+                    &skipped,
+                    cli.verbose,
+                ) {
                     eprintln!("Error cleaning context folder: {}", e);
                 }
                 return;
             }
             Commands::Combine(args) => {
-                let config = load_config(&cli.config).expect("Failed to load config");
+                let (config, _) = match load_profile(&cli) {
+                    Ok(loaded) => loaded,
+                    Err(e) => {
+                        eprintln!("Error loading {}: {}", cli.config, e);
+                        return;
+                    }
+                };
                 match combine::handle_combine_action(args, &config, cli.verbose) {
                     Ok(_) => {}
                     Err(e) => eprintln!("Error combining files: {}", e),
                 }
                 return;
             }
+            Commands::Migrate { dry_run, yes } => {
+                if let Err(e) = run_migrate(&cli.config, *dry_run, *yes) {
+                    eprintln!("Error migrating {}: {}", cli.config, e);
+                }
+                return;
+            }
+            Commands::Schema => {
+                println!("{}", config::config_schema());
+                return;
+            }
+            Commands::Sync(_) => {}
         }
     }
 
     if cli.verbose {
         println!("copilot-context: verbose mode enabled");
     }
-    let config_path = cli.config;
+    let config_path = &cli.config;
     if cli.verbose {
         println!("copilot-context: loading config from {}", config_path);
     }
-    let mut config = config::load_config(&config_path).expect("Failed to load config");
+    let selection = match &cli.command {
+        Some(Commands::Sync(selection)) => selection.clone(),
+        _ => SourceSelection::default(),
+    };
+    let (config, skipped) = match load_selection(&cli, &selection) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Error loading {}: {}", cli.config, e);
+            return;
+        }
+    };
     if cli.verbose {
         println!("copilot-context: loaded config: {:?}", config);
+        if cli.profile.is_some() || !selection.is_empty() {
+            println!(
+                "copilot-context: syncing {} of {} sources",
+                config.sources.len(),
+                config.sources.len() + skipped.len()
+            );
+        }
     }
 
-    if config.dest.is_none() {
-        config.dest = Some(".copilot-context".to_string());
-    }
+    // Relative paths in the config are resolved against the config file's directory
+    let config_dir = config.base_dir.clone();
+    let dest = config.context_dir();
 
     if cli.verbose {
-        println!("copilot-context: destination directory: {:?}", config.dest);
+        println!("copilot-context: destination directory: {}", dest.display());
     }
 
-    let dest = config.dest.as_ref().unwrap();
-
-    std::fs::create_dir_all(dest).expect("Failed to create destination directory");
-    std::env::set_current_dir(dest).expect("Failed to change working directory");
+    std::fs::create_dir_all(&dest).expect("Failed to create destination directory");
+    std::env::set_current_dir(&dest).expect("Failed to change working directory");
 
     // Update root to the new current directory after changing into .copilot-context
     let root = std::env::current_dir().expect("Failed to get current directory");
 
     println!("copilot-context: initializing context folder...");
-    for source in config.sources {
+    let mut summary = summary::RunSummary::default();
+    // A source's limits and filters only touch its own files, not those of sources
+    // whose dest is nested inside its dest
+    let dests: Vec<String> = config
+        .sources
+        .iter()
+        .chain(&skipped)
+        .map(|s| s.dest().to_string())
+        .collect();
+    // Files of the sources synced in this run; only these are capped or redacted
+    let mut synced: Vec<std::path::PathBuf> = Vec::new();
+    let mut manifest = manifest::Manifest::load(&root).unwrap_or_else(|e| {
+        eprintln!("copilot-context: ignoring unreadable manifest: {}", e);
+        manifest::Manifest::default()
+    });
+    // Sources left out by the profile keep their manifest records
+    let configured: Vec<String> = config
+        .sources
+        .iter()
+        .chain(&skipped)
+        .map(|s| s.name().to_string())
+        .collect();
+    // Sources run after the sources they depend on
+    let stages = deps::schedule(config.sources).expect("Failed to schedule sources");
+    if cli.verbose && stages.len() > 1 {
+        for (i, stage) in stages.iter().enumerate() {
+            let names: Vec<&str> = stage.iter().map(|s| s.name()).collect();
+            println!("copilot-context: stage {}: {}", i + 1, names.join(", "));
+        }
+    }
+    let mut failed = BTreeSet::new();
+    for source in stages.into_iter().flatten() {
+        let source_name = source.name().to_string();
+        if let Some(dependency) = source
+            .options()
+            .depends_on
+            .iter()
+            .find(|d| failed.contains(*d))
+        {
+            eprintln!(
+                "copilot-context: skipping {} because {} failed",
+                source_name, dependency
+            );
+            if let Some(record) = manifest.sources.get_mut(&source_name) {
+                record.failed = true;
+            }
+            failed.insert(source_name);
+            continue;
+        }
+        let source_dest = source.dest().to_string();
+        let source_root = root.join(&source_dest);
+        let source_limits = source.options().limits.with_fallback(&config.limits);
+        let content_filter = content::ContentFilter::new(
+            &source.options().content_include,
+            &source.options().content_exclude,
+        );
+        let previous = manifest
+            .sources
+            .get(&source_name)
+            .cloned()
+            .unwrap_or_default();
+        let previous_partial = previous.partial;
+        let mut partial = BTreeMap::new();
+        let mut rev = None;
         match source {
             config::Source::Repo {
                 name,
@@ -247,17 +495,33 @@ fn main() {
                 branch,
                 dest,
                 files,
+                ..
             } => {
                 if cli.verbose {
                     println!("copilot-context: processing repo source: {}", name);
                 }
-                if let Err(e) = git::fetch_repo(&repo, &dest, branch.as_deref(), cli.verbose) {
-                    eprintln!("copilot-context: error fetching repo {}: {}", name, e);
+                // An existing checkout is reused as-is, including files already trimmed
+                let reused = std::path::Path::new(&dest).exists();
+                match git::fetch_repo(&repo, &dest, branch.as_deref(), cli.verbose) {
+                    Ok(fetched) => rev = fetched.or(previous.rev),
+                    Err(e) => {
+                        eprintln!("copilot-context: error fetching repo {}: {}", name, e);
+                        failed.insert(name);
+                    }
                 }
                 if let Some(files) = files {
-                    if let Err(e) = files_func(&root.join(dest), files, cli.verbose) {
+                    if let Err(e) = files_func(&source_root, &files, cli.verbose) {
                         eprintln!("copilot-context: error applying files rules: {}", e);
                     }
+                    let previous = if reused {
+                        &previous_partial
+                    } else {
+                        &BTreeMap::new()
+                    };
+                    match select_func(&root, &source_root, &files, previous, cli.verbose) {
+                        Ok(selected) => partial = selected,
+                        Err(e) => eprintln!("copilot-context: error extracting selections: {}", e),
+                    }
                 }
             }
             config::Source::Url {
@@ -265,17 +529,24 @@ fn main() {
                 url,
                 dest,
                 files,
+                headers,
+                ..
             } => {
                 if cli.verbose {
                     println!("copilot-context: processing URL source: {}", name);
                 }
-                if let Err(e) = fetch::fetch_url(&url, &dest, cli.verbose) {
+                if let Err(e) = fetch::fetch_url(&url, &dest, &headers.0, cli.verbose) {
                     eprintln!("copilot-context: error fetching url {}: {}", name, e);
+                    failed.insert(name);
                 }
                 if let Some(files) = files {
-                    if let Err(e) = files_func(&root, files, cli.verbose) {
+                    if let Err(e) = files_func(&source_root, &files, cli.verbose) {
                         eprintln!("copilot-context: error applying files rules: {}", e);
                     }
+                    match select_func(&root, &source_root, &files, &BTreeMap::new(), cli.verbose) {
+                        Ok(selected) => partial = selected,
+                        Err(e) => eprintln!("copilot-context: error extracting selections: {}", e),
+                    }
                 }
             }
             config::Source::Path {
@@ -283,16 +554,12 @@ fn main() {
                 path,
                 dest,
                 files,
+                ..
             } => {
                 if cli.verbose {
                     println!("copilot-context: processing path source: {}", name);
                 }
-                let project_root = std::env::current_dir()
-                    .expect("Failed to get current directory")
-                    .parent()
-                    .unwrap()
-                    .to_path_buf();
-                let abs_source = project_root.join(path);
+                let abs_source = config::resolve_path(&config_dir, &path);
                 let abs_source_str = abs_source
                     .as_path()
                     .to_str()
@@ -302,29 +569,202 @@ fn main() {
                 }
                 if let Err(e) = copy::copy_local(abs_source_str, &dest, cli.verbose) {
                     eprintln!("copilot-context: error copying path {}: {}", name, e);
+                    failed.insert(name);
                 }
                 if let Some(files) = files {
-                    if let Err(e) = files_func(&root, files, cli.verbose) {
+                    if let Err(e) = files_func(&source_root, &files, cli.verbose) {
                         eprintln!("copilot-context: error applying files rules: {}", e);
                     }
+                    match select_func(&root, &source_root, &files, &BTreeMap::new(), cli.verbose) {
+                        Ok(selected) => partial = selected,
+                        Err(e) => eprintln!("copilot-context: error extracting selections: {}", e),
+                    }
                 }
             }
-            config::Source::Sh { name, script, dest } => {
+            config::Source::Sh {
+                name,
+                script,
+                dest,
+                files,
+                ..
+            } => {
                 if cli.verbose {
                     println!("copilot-context: processing sh source: {}", name);
                 }
-                if let Err(e) =
-                    sh::run_script(&script, &std::path::PathBuf::from(dest), cli.verbose)
-                {
+                if let Err(e) = sh::run_script(
+                    &script,
+                    &std::path::PathBuf::from(dest),
+                    // Included scripts see the directory of the file declaring them
+                    config.source_dirs.get(&name).unwrap_or(&config_dir),
+                    cli.verbose,
+                ) {
                     eprintln!("copilot-context: error running script {}: {}", name, e);
+                    failed.insert(name);
+                }
+                if let Some(files) = files {
+                    if let Err(e) = files_func(&source_root, &files, cli.verbose) {
+                        eprintln!("copilot-context: error applying files rules: {}", e);
+                    }
+                    match select_func(&root, &source_root, &files, &BTreeMap::new(), cli.verbose) {
+                        Ok(selected) => partial = selected,
+                        Err(e) => eprintln!("copilot-context: error extracting selections: {}", e),
+                    }
                 }
             }
         }
+        let files = manifest::source_files(&root, &source_dest, &dests);
+        match content_filter
+            .and_then(|filter| content::apply_content_filter(&files, &filter, cli.verbose))
+        {
+            Ok(dropped) => summary.record(&source_name, dropped),
+            Err(e) => eprintln!(
+                "copilot-context: error applying content filters for {}: {}",
+                source_name, e
+            ),
+        }
+        let files = manifest::source_files(&root, &source_dest, &dests);
+        match limits::enforce_limits(&files, &source_limits, cli.verbose) {
+            Ok(dropped) => summary.record(&source_name, dropped),
+            Err(e) => eprintln!(
+                "copilot-context: error enforcing limits for {}: {}",
+                source_name, e
+            ),
+        }
+        synced.extend(manifest::source_files(&root, &source_dest, &dests));
+        let source_failed = failed.contains(&source_name);
+        let synced_at = if source_failed {
+            previous.synced_at
+        } else {
+            Some(unix_now())
+        };
+        manifest.sources.insert(
+            source_name,
+            manifest::SourceRecord {
+                dest: manifest::manifest_key(&root, &source_root),
+                rev,
+                partial,
+                synced_at,
+                failed: source_failed,
+            },
+        );
+    }
+
+    // The global total size caps the whole context folder. Files of sources that were
+    // not synced in this run count toward it, but are never dropped.
+    if config.limits.max_total_size.is_some() {
+        let folder_limits = limits::Limits {
+            max_total_size: config.limits.max_total_size,
+            ..Default::default()
+        };
+        let owned: BTreeSet<&std::path::PathBuf> = synced.iter().collect();
+        let reserved: u64 = manifest::source_files(&root, ".", &[])
+            .iter()
+            .filter(|path| !owned.contains(path))
+            .filter_map(|path| path.metadata().ok())
+            .map(|metadata| metadata.len())
+            .sum();
+        match limits::enforce_limits_after(&synced, reserved, &folder_limits, cli.verbose) {
+            Ok(dropped) => summary.record("(global)", dropped),
+            Err(e) => eprintln!("copilot-context: error enforcing global limits: {}", e),
+        }
+    }
+
+    // Files dropped by the global cap are gone
+    synced.retain(|path| path.exists());
+    if config.redact.enabled {
+        match redact::Redactor::new(&config.redact)
+            .and_then(|redactor| redact::redact_files(&synced, &redactor, cli.verbose))
+        {
+            Ok(redacted) => summary.record_redacted(redacted),
+            Err(e) => eprintln!("copilot-context: error redacting secrets: {}", e),
+        }
+    }
+
+    // Forget sources that are no longer configured and files that were dropped
+    manifest.sources.retain(|name, _| configured.contains(name));
+    for record in manifest.sources.values_mut() {
+        record.partial.retain(|path, _| root.join(path).exists());
+    }
+    if let Err(e) = manifest.save(&root) {
+        eprintln!("copilot-context: error writing manifest: {}", e);
+    }
+    summary.print(&root);
+}
+
+/// Show the diff from migrating `path` to the current version, then write it once
+/// confirmed (or right away with `yes`)
+fn run_migrate(path: &str, dry_run: bool, yes: bool) -> Result<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let Some(migrated) = migrate::migrate(&text)? else {
+        println!(
+            "{} is already at version {}.",
+            path,
+            migrate::CONFIG_VERSION
+        );
+        return Ok(());
+    };
+    print!("{}", migrate::diff(path, &text, &migrated));
+    if dry_run {
+        return Ok(());
+    }
+    if !yes {
+        print!("Apply these changes to {}? [y/N] ", path);
+        std::io::Write::flush(&mut std::io::stdout()).map_err(|e| e.to_string())?;
+        let mut answer = String::new();
+        std::io::stdin()
+            .read_line(&mut answer)
+            .map_err(|e| e.to_string())?;
+        if !answer.trim().eq_ignore_ascii_case("y") {
+            println!("Not migrated.");
+            return Ok(());
+        }
     }
+    std::fs::write(path, migrated).map_err(|e| e.to_string())?;
+    println!("Migrated {} to version {}.", path, migrate::CONFIG_VERSION);
+    Ok(())
+}
+
+/// Current time in seconds since the Unix epoch
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Load the config and narrow it to the `--profile`, if given. Also returns the
+/// sources the profile left out.
+fn load_profile(cli: &Cli) -> Result<(config::ContextConfig, Vec<config::Source>), String> {
+    let mut config = config::load_config(&cli.config).map_err(|e| e.to_string())?;
+    let skipped = match &cli.profile {
+        Some(profile) => config.apply_profile(profile)?,
+        None => Vec::new(),
+    };
+    Ok((config, skipped))
 }
 
-fn files_func(root: &std::path::Path, files: Vec<String>, verbose: bool) -> Result<(), String> {
-    let rules = parse_file_rules(&files);
+/// Like `load_profile`, then narrowed further by `--only`/`--skip`/`--tag`. Disabled
+/// sources are always left out.
+fn load_selection(
+    cli: &Cli,
+    selection: &SourceSelection,
+) -> Result<(config::ContextConfig, Vec<config::Source>), String> {
+    let (mut config, mut skipped) = load_profile(cli)?;
+    skipped.extend(selection.apply(&mut config)?);
+    for source in config.take_disabled() {
+        if cli.verbose {
+            println!(
+                "copilot-context: skipping disabled source {}",
+                source.name()
+            );
+        }
+        skipped.push(source);
+    }
+    Ok((config, skipped))
+}
+
+fn files_func(root: &std::path::Path, files: &[String], verbose: bool) -> Result<(), String> {
+    let rules = parse_file_rules(files)?;
     let matches = match_files_and_mark(root, &rules);
     for (path, keep) in matches {
         if !keep {
@@ -357,3 +797,49 @@ fn files_func(root: &std::path::Path, files: Vec<String>, verbose: bool) -> Resu
     }
     Ok(())
 }
+
+/// Trim files matched by selector rules (`path#L10-20`, `README.md#heading:Usage`) under
+/// `source_root`, returning the partial files keyed by path relative to `root`.
+///
+/// Files listed in `previous` were trimmed by an earlier run and are not trimmed again.
+fn select_func(
+    root: &std::path::Path,
+    source_root: &std::path::Path,
+    files: &[String],
+    previous: &BTreeMap<String, String>,
+    verbose: bool,
+) -> Result<BTreeMap<String, String>, String> {
+    let rules = parse_file_rules(files)?;
+    let mut partial = BTreeMap::new();
+    for (path, selector) in extract::match_selections(source_root, &rules) {
+        let key = manifest::manifest_key(root, &path);
+        if let Some(previous_selector) = previous.get(&key) {
+            if *previous_selector != selector.to_string() {
+                eprintln!(
+                    "copilot-context: {} was already trimmed to '{}'; remove it to extract '{}'",
+                    key, previous_selector, selector
+                );
+            }
+            partial.insert(key, previous_selector.clone());
+            continue;
+        }
+        match extract::extract_file(&path, &selector) {
+            Ok(true) => {
+                if verbose {
+                    println!("copilot-context: extracted '{}' from {}", selector, key);
+                }
+                partial.insert(key, selector.to_string());
+            }
+            Ok(false) => eprintln!(
+                "copilot-context: selection '{}' not found in {}, keeping the whole file",
+                selector, key
+            ),
+            // One unreadable file doesn't stop the selections in the others
+            Err(e) => eprintln!(
+                "copilot-context: skipping selection '{}' in {}: {}",
+                selector, key, e
+            ),
+        }
+    }
+    Ok(partial)
+}
@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Name of the manifest file kept at the root of the context folder
+pub const MANIFEST_FILE: &str = ".copilot-context-manifest.toml";
+
+/// Record of what each source wrote into the context folder
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(default)]
+    pub sources: BTreeMap<String, SourceRecord>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceRecord {
+    pub dest: String,
+    /// Commit checked out for repo sources
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    /// Files trimmed by a selector, keyed by path relative to the context folder
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub partial: BTreeMap<String, String>,
+    /// Unix time of the last successful sync
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub synced_at: Option<u64>,
+    /// Whether the last sync of this source failed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub failed: bool,
+}
+
+impl Manifest {
+    /// Load the manifest from `context_dir`, or an empty one if there is none yet
+    pub fn load(context_dir: &Path) -> Result<Manifest, String> {
+        let path = context_dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(Manifest::default());
+        }
+        let content = std::fs::read_to_string(&path)
+            .map_err(|e| format!("failed to read '{}': {}", path.display(), e))?;
+        toml::from_str(&content).map_err(|e| format!("failed to parse '{}': {}", path.display(), e))
+    }
+
+    pub fn save(&self, context_dir: &Path) -> Result<(), String> {
+        let path = context_dir.join(MANIFEST_FILE);
+        let content = toml::to_string_pretty(self)
+            .map_err(|e| format!("failed to serialize manifest: {}", e))?;
+        std::fs::write(&path, content)
+            .map_err(|e| format!("failed to write '{}': {}", path.display(), e))
+    }
+}
+
+/// Manifest key for `path` inside `context_dir`, always using `/` separators
+pub fn manifest_key(context_dir: &Path, path: &Path) -> String {
+    path.strip_prefix(context_dir)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Files a source writing to `dest` owns: everything under it (a directory or a single
+/// file) except the manifest and anything under `other_dests` nested inside it, which
+/// belongs to those sources. Sorted by path.
+pub fn source_files(context_dir: &Path, dest: &str, other_dests: &[String]) -> Vec<PathBuf> {
+    let root = context_dir.join(dest);
+    let nested: Vec<PathBuf> = other_dests
+        .iter()
+        .map(|other| context_dir.join(other))
+        .filter(|other| other != &root && other.starts_with(&root))
+        .collect();
+    let manifest = context_dir.join(MANIFEST_FILE);
+    let mut files: Vec<PathBuf> = WalkDir::new(&root)
+        .into_iter()
+        .filter_entry(|entry| !nested.iter().any(|n| entry.path() == n.as_path()))
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file() && entry.path() != manifest)
+        .map(|entry| entry.into_path())
+        .collect();
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_load_missing_manifest() {
+        let dir = tempdir().unwrap();
+        let manifest = Manifest::load(dir.path()).unwrap();
+        assert!(manifest.sources.is_empty());
+    }
+
+    #[test]
+    fn test_save_and_load_manifest() {
+        let dir = tempdir().unwrap();
+        let mut manifest = Manifest::default();
+        let mut record = SourceRecord {
+            dest: "vendor/lib".to_string(),
+            rev: Some("3f786850e387550fdab836ed7e6dc881de23001b".to_string()),
+            ..Default::default()
+        };
+        record
+            .partial
+            .insert("vendor/lib/src/lib.rs".to_string(), "L100-220".to_string());
+        manifest.sources.insert("lib".to_string(), record.clone());
+        manifest.save(dir.path()).unwrap();
+
+        let loaded = Manifest::load(dir.path()).unwrap();
+        assert_eq!(loaded.sources.get("lib"), Some(&record));
+    }
+
+    #[test]
+    fn test_source_files_skip_nested_dests() {
+        let dir = tempdir().unwrap();
+        for file in [
+            "notes.md",
+            "docs/guide.md",
+            "docs/api/index.md",
+            MANIFEST_FILE,
+        ] {
+            let path = dir.path().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "x").unwrap();
+        }
+        let keys = |dest: &str, others: &[&str]| -> Vec<String> {
+            let others: Vec<String> = others.iter().map(|o| o.to_string()).collect();
+            source_files(dir.path(), dest, &others)
+                .iter()
+                .map(|path| manifest_key(dir.path(), path))
+                .collect()
+        };
+        assert_eq!(keys("docs", &["docs/api", "."]), vec!["docs/guide.md"]);
+        assert_eq!(keys("docs/api", &["docs"]), vec!["docs/api/index.md"]);
+        assert_eq!(keys(".", &["docs", "."]), vec!["notes.md"]);
+        assert!(keys("nope", &[]).is_empty());
+    }
+
+    #[test]
+    fn test_manifest_key() {
+        let root = Path::new("/ctx");
+        assert_eq!(
+            manifest_key(root, &root.join("vendor").join("a.rs")),
+            "vendor/a.rs"
+        );
+    }
+}
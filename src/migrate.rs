@@ -0,0 +1,226 @@
+use similar::TextDiff;
+use toml_edit::{DocumentMut, Item, Table, Value};
+
+/// Version of the `context.toml` format this build reads and writes
+pub const CONFIG_VERSION: u8 = 1;
+
+/// Rewrites a config from one version to the next
+type Migration = fn(&mut DocumentMut) -> Result<(), String>;
+
+/// Upgrades from each older version to the next: `MIGRATIONS[n]` turns version `n`
+/// into version `n + 1`
+const MIGRATIONS: [Migration; CONFIG_VERSION as usize] = [upgrade_to_1];
+
+/// `version` of a config, with configs that predate the key counting as version 0
+fn version_of(table: &toml::Table) -> Result<i64, String> {
+    match table.get("version") {
+        None => Ok(0),
+        Some(toml::Value::Integer(version)) => Ok(*version),
+        Some(other) => Err(format!("`version` must be a number, not {}", other)),
+    }
+}
+
+/// Error unless the config text is at the version this build understands
+pub fn check_version(text: &str) -> Result<(), String> {
+    let table: toml::Table = toml::from_str(text).map_err(|e| e.to_string())?;
+    let version = version_of(&table)?;
+    let current = i64::from(CONFIG_VERSION);
+    if version > current {
+        return Err(format!(
+            "config version {} is newer than this copilot-context supports (up to {}); \
+             upgrade copilot-context to use it",
+            version, current
+        ));
+    }
+    if version == 0 && !table.contains_key("version") {
+        return Err("config has no `version`; run `copilot-context migrate` to add it".to_string());
+    }
+    if version < current {
+        return Err(format!(
+            "config version {} is out of date (current is {}); \
+             run `copilot-context migrate` to upgrade it",
+            version, current
+        ));
+    }
+    Ok(())
+}
+
+/// Upgrade config text to the current version, keeping comments and layout.
+/// Returns `None` when it is already current.
+pub fn migrate(text: &str) -> Result<Option<String>, String> {
+    let table: toml::Table = toml::from_str(text).map_err(|e| e.to_string())?;
+    let version = version_of(&table)?;
+    if version > i64::from(CONFIG_VERSION) {
+        return Err(format!(
+            "config version {} is newer than this copilot-context supports (up to {})",
+            version, CONFIG_VERSION
+        ));
+    }
+    if version == i64::from(CONFIG_VERSION) {
+        return Ok(None);
+    }
+    let mut doc: DocumentMut = text.parse().map_err(|e| format!("{}", e))?;
+    for (from, step) in MIGRATIONS.iter().enumerate().skip(version.max(0) as usize) {
+        step(&mut doc)?;
+        if let Some(value) = doc.get_mut("version").and_then(Item::as_value_mut) {
+            let decor = value.decor().clone();
+            *value = toml_edit::Value::from(from as i64 + 1);
+            *value.decor_mut() = decor;
+        }
+    }
+    let migrated = doc.to_string();
+    toml::from_str::<crate::config::ContextConfig>(&migrated)
+        .map_err(|e| format!("migrated config is not valid: {}", e))?;
+    Ok(Some(migrated))
+}
+
+/// Version 0 to 1: `${` became placeholder syntax, so sources written before keep
+/// their literal `${` (e.g. shell variables in scripts) as `$${`. Then the config gets
+/// `version = 1`.
+fn upgrade_to_1(doc: &mut DocumentMut) -> Result<(), String> {
+    match doc.get_mut("sources") {
+        Some(Item::ArrayOfTables(sources)) => {
+            for source in sources.iter_mut() {
+                for (_, item) in source.iter_mut() {
+                    escape_item(item);
+                }
+            }
+        }
+        Some(item) => escape_item(item),
+        None => {}
+    }
+    add_version(doc)
+}
+
+fn escape_item(item: &mut Item) {
+    match item {
+        Item::Value(value) => escape_value(value),
+        Item::Table(table) => {
+            for (_, item) in table.iter_mut() {
+                escape_item(item);
+            }
+        }
+        Item::ArrayOfTables(tables) => {
+            for table in tables.iter_mut() {
+                for (_, item) in table.iter_mut() {
+                    escape_item(item);
+                }
+            }
+        }
+        Item::None => {}
+    }
+}
+
+/// Write each `${` in the string values of `value` as `$${`, keeping comments
+fn escape_value(value: &mut Value) {
+    match value {
+        Value::String(s) if s.value().contains("${") => {
+            let decor = s.decor().clone();
+            let mut escaped = Value::from(s.value().replace("${", "$${"));
+            *escaped.decor_mut() = decor;
+            *value = escaped;
+        }
+        Value::Array(array) => array.iter_mut().for_each(escape_value),
+        Value::InlineTable(table) => {
+            for (_, value) in table.iter_mut() {
+                escape_value(value);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Configs written before `version` existed get `version = 1` as their first key
+fn add_version(doc: &mut DocumentMut) -> Result<(), String> {
+    let root = doc.as_table();
+    let keys: Vec<String> = root.iter().map(|(key, _)| key.to_string()).collect();
+    let mut upgraded = Table::new();
+    upgraded.insert("version", toml_edit::value(1));
+    for name in &keys {
+        if let Some((key, item)) = root.get_key_value(name) {
+            upgraded.insert_formatted(key, item.clone());
+        }
+    }
+    // The file's opening comment stays at the top, above `version`
+    let leading = keys
+        .first()
+        .and_then(|first| upgraded.key_mut(first))
+        .and_then(|mut key| {
+            let prefix = key.leaf_decor().prefix().cloned();
+            key.leaf_decor_mut().set_prefix("");
+            prefix
+        });
+    if let (Some(prefix), Some(mut version)) = (leading, upgraded.key_mut("version")) {
+        version.leaf_decor_mut().set_prefix(prefix);
+    }
+    *doc.as_table_mut() = upgraded;
+    Ok(())
+}
+
+/// Unified diff between the current and migrated config
+pub fn diff(path: &str, old: &str, new: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(path, path)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_version() {
+        assert!(check_version("version = 1\nsources = []\n").is_ok());
+        let old = check_version("sources = []\n").unwrap_err();
+        assert!(old.contains("copilot-context migrate"), "{}", old);
+        let new = check_version("version = 9\nsources = []\n").unwrap_err();
+        assert!(new.contains("upgrade copilot-context"), "{}", new);
+    }
+
+    #[test]
+    fn test_migrate_unversioned_config() {
+        let old = "# Team context\ndest = \"ctx\" # output\n\n[[sources]]\ntype = \"url\"\nname = \"spec\"\nurl = \"https://example.com/spec\"\ndest = \"spec\"\n";
+        let migrated = migrate(old).unwrap().unwrap();
+        assert_eq!(
+            migrated,
+            "# Team context\nversion = 1\ndest = \"ctx\" # output\n\n[[sources]]\ntype = \"url\"\nname = \"spec\"\nurl = \"https://example.com/spec\"\ndest = \"spec\"\n"
+        );
+        assert!(check_version(&migrated).is_ok());
+        assert_eq!(migrate(&migrated).unwrap(), None);
+
+        let diff = diff("context.toml", old, &migrated);
+        assert!(diff.contains("+version = 1"), "{}", diff);
+    }
+
+    #[test]
+    fn test_migrate_escapes_placeholders() {
+        let old = "[[sources]]\ntype = \"sh\"\nname = \"home\"\nscript = \"echo ${HOME} $${x}\" # shell\ndest = \"home\"\n\n[[sources]]\ntype = \"path\"\nname = \"odd\"\npath = \"odd\"\ndest = \"odd\"\nfiles = [\"${odd}/*.md\"]\n";
+        let migrated = migrate(old).unwrap().unwrap();
+        assert!(
+            migrated.contains("script = \"echo $${HOME} $$${x}\" # shell\n"),
+            "{}",
+            migrated
+        );
+        assert!(
+            migrated.contains("files = [\"$${odd}/*.md\"]"),
+            "{}",
+            migrated
+        );
+
+        // Expanding the migrated config gives back the old values
+        let mut config: crate::config::ContextConfig = toml::from_str(&migrated).unwrap();
+        crate::vars::interpolate_config(&mut config).unwrap();
+        match &config.sources[0] {
+            crate::config::Source::Sh { script, .. } => assert_eq!(script, "echo ${HOME} $${x}"),
+            other => panic!("unexpected source {:?}", other),
+        }
+        match &config.sources[1] {
+            crate::config::Source::Path { files, .. } => {
+                assert_eq!(files.as_deref(), Some(&["${odd}/*.md".to_string()][..]))
+            }
+            other => panic!("unexpected source {:?}", other),
+        }
+    }
+}
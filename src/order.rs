@@ -0,0 +1,185 @@
+use clap::ValueEnum;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Order of files in combined output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FileOrder {
+    /// Alphabetically by path
+    Alpha,
+    /// Highest priority first
+    Priority,
+    /// Most recently modified first
+    Mtime,
+    /// Smallest first
+    Size,
+    /// Rust files after the file declaring them with `mod`
+    Dependency,
+}
+
+/// Sort `paths` in place. Ties keep their current order.
+pub fn order_files(paths: &mut Vec<PathBuf>, order: FileOrder, priority: impl Fn(&Path) -> i32) {
+    match order {
+        FileOrder::Alpha => paths.sort(),
+        FileOrder::Priority => paths.sort_by_key(|p| std::cmp::Reverse(priority(p))),
+        FileOrder::Mtime => paths.sort_by_cached_key(|p| {
+            std::cmp::Reverse(fs::metadata(p).and_then(|m| m.modified()).ok())
+        }),
+        FileOrder::Size => paths.sort_by_cached_key(|p| fs::metadata(p).map(|m| m.len()).ok()),
+        FileOrder::Dependency => *paths = dependency_order(paths),
+    }
+}
+
+/// Module names declared with `mod name;` in Rust source
+pub fn rust_mod_declarations(text: &str) -> Vec<String> {
+    static MOD_DECL: OnceLock<Regex> = OnceLock::new();
+    let re = MOD_DECL.get_or_init(|| {
+        Regex::new(r"(?m)^\s*(?:pub(?:\([^)]*\))?\s+)?mod\s+([A-Za-z_][A-Za-z0-9_]*)\s*;")
+            .expect("mod declaration regex is valid")
+    });
+    re.captures_iter(text).map(|c| c[1].to_string()).collect()
+}
+
+/// Files a Rust file's `mod` declarations point to, if they are among `paths`
+fn declared_modules(path: &Path, paths: &HashSet<&Path>) -> Vec<PathBuf> {
+    if path.extension().is_none_or(|e| e != "rs") {
+        return Vec::new();
+    }
+    let Ok(text) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    let parent = path.parent().unwrap_or(Path::new(""));
+    // `lib.rs`, `main.rs` and `mod.rs` own their directory; `foo.rs` owns `foo/`
+    let module_dir = match path.file_stem().and_then(|s| s.to_str()) {
+        Some("lib" | "main" | "mod") => parent.to_path_buf(),
+        Some(stem) => parent.join(stem),
+        None => return Vec::new(),
+    };
+    rust_mod_declarations(&text)
+        .into_iter()
+        .filter_map(|name| {
+            [
+                module_dir.join(format!("{}.rs", name)),
+                module_dir.join(&name).join("mod.rs"),
+            ]
+            .into_iter()
+            .find(|candidate| paths.contains(candidate.as_path()))
+        })
+        .collect()
+}
+
+/// Depth-first order where every Rust file comes right before the modules it declares
+fn dependency_order(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut sorted = paths.to_vec();
+    sorted.sort();
+    let set: HashSet<&Path> = sorted.iter().map(PathBuf::as_path).collect();
+    let children: HashMap<&Path, Vec<PathBuf>> = sorted
+        .iter()
+        .map(|p| (p.as_path(), declared_modules(p, &set)))
+        .collect();
+    let declared: HashSet<&Path> = children.values().flatten().map(PathBuf::as_path).collect();
+
+    fn visit(
+        path: &Path,
+        children: &HashMap<&Path, Vec<PathBuf>>,
+        seen: &mut HashSet<PathBuf>,
+        out: &mut Vec<PathBuf>,
+    ) {
+        if !seen.insert(path.to_path_buf()) {
+            return;
+        }
+        out.push(path.to_path_buf());
+        for child in children.get(path).into_iter().flatten() {
+            visit(child, children, seen, out);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut out = Vec::with_capacity(sorted.len());
+    // Start from files nobody declares, then pick up anything left (e.g. `mod` cycles)
+    for path in sorted.iter().filter(|p| !declared.contains(p.as_path())) {
+        visit(path, &children, &mut seen, &mut out);
+    }
+    for path in &sorted {
+        visit(path, &children, &mut seen, &mut out);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_rust_mod_declarations() {
+        let text = "mod a;\npub mod b;\npub(crate) mod c;\nmod inline { }\n// mod d;\n";
+        assert_eq!(rust_mod_declarations(text), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_dependency_order() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir_all(src.join("net")).unwrap();
+        fs::write(src.join("main.rs"), "mod net;\nmod util;\n").unwrap();
+        fs::write(src.join("util.rs"), "").unwrap();
+        fs::write(src.join("net/mod.rs"), "mod http;\n").unwrap();
+        fs::write(src.join("net/http.rs"), "").unwrap();
+        fs::write(dir.path().join("README.md"), "").unwrap();
+
+        let mut paths = vec![
+            src.join("util.rs"),
+            src.join("net/http.rs"),
+            dir.path().join("README.md"),
+            src.join("net/mod.rs"),
+            src.join("main.rs"),
+        ];
+        order_files(&mut paths, FileOrder::Dependency, |_| 0);
+        let names: Vec<_> = paths
+            .iter()
+            .map(|p| {
+                p.strip_prefix(dir.path())
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "README.md",
+                "src/main.rs",
+                "src/net/mod.rs",
+                "src/net/http.rs",
+                "src/util.rs"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_priority_and_size_order() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("big"), "xxxx").unwrap();
+        fs::write(dir.path().join("small"), "x").unwrap();
+        let mut paths = vec![dir.path().join("big"), dir.path().join("small")];
+
+        order_files(&mut paths, FileOrder::Size, |_| 0);
+        assert!(paths[0].ends_with("small"));
+
+        order_files(&mut paths, FileOrder::Priority, |p| {
+            if p.ends_with("big") {
+                5
+            } else {
+                0
+            }
+        });
+        assert!(paths[0].ends_with("big"));
+    }
+}
@@ -0,0 +1,128 @@
+use clap::Args;
+use glob::Pattern;
+
+use crate::config::{ContextConfig, Source};
+
+/// Command-line filters choosing which sources to work on
+#[derive(Args, Debug, Default, Clone)]
+pub struct SourceSelection {
+    /// Only these sources; glob patterns like `api-*` are allowed (repeatable)
+    #[clap(long, num_args = 1..)]
+    pub only: Vec<String>,
+    /// Leave out these sources; glob patterns are allowed (repeatable)
+    #[clap(long)]
+    pub skip: Vec<String>,
+    /// Only sources with this tag (repeatable)
+    #[clap(long)]
+    pub tag: Vec<String>,
+}
+
+impl SourceSelection {
+    pub fn is_empty(&self) -> bool {
+        self.only.is_empty() && self.skip.is_empty() && self.tag.is_empty()
+    }
+
+    /// Keep only the selected sources in `config`, returning the ones left out.
+    /// A source is selected when it matches `--only` or has a `--tag` (or neither
+    /// is given) and doesn't match `--skip`.
+    pub fn apply(&self, config: &mut ContextConfig) -> Result<Vec<Source>, String> {
+        let only = compile(&self.only)?;
+        let skip = compile(&self.skip)?;
+        if let Some(unmatched) = only
+            .iter()
+            .find(|p| !config.sources.iter().any(|s| p.matches(s.name())))
+        {
+            return Err(format!("--only '{}' matches no source", unmatched));
+        }
+        let wanted = |source: &Source| {
+            let chosen = (only.is_empty() && self.tag.is_empty())
+                || only.iter().any(|p| p.matches(source.name()))
+                || source.options().tags.iter().any(|t| self.tag.contains(t));
+            chosen && !skip.iter().any(|p| p.matches(source.name()))
+        };
+        let (selected, skipped) = std::mem::take(&mut config.sources)
+            .into_iter()
+            .partition(wanted);
+        config.sources = selected;
+        Ok(skipped)
+    }
+}
+
+fn compile(patterns: &[String]) -> Result<Vec<Pattern>, String> {
+    patterns
+        .iter()
+        .map(|p| Pattern::new(p).map_err(|e| format!("invalid source pattern '{}': {}", p, e)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SourceOptions;
+
+    fn config() -> ContextConfig {
+        let source = |name: &str, tags: &[&str]| Source::Url {
+            name: name.to_string(),
+            url: format!("https://example.com/{}", name),
+            dest: name.to_string(),
+            files: None,
+            headers: Default::default(),
+            options: SourceOptions {
+                tags: tags.iter().map(|t| t.to_string()).collect(),
+                ..Default::default()
+            },
+        };
+        ContextConfig {
+            sources: vec![
+                source("api-v1", &[]),
+                source("api-v2", &["core"]),
+                source("docs", &["core"]),
+                source("changelog", &[]),
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn names(config: &ContextConfig) -> Vec<&str> {
+        config.sources.iter().map(|s| s.name()).collect()
+    }
+
+    #[test]
+    fn test_select_sources() {
+        let mut all = config();
+        assert!(SourceSelection::default()
+            .apply(&mut all)
+            .unwrap()
+            .is_empty());
+        assert_eq!(all.sources.len(), 4);
+
+        let mut only = config();
+        let skipped = SourceSelection {
+            only: vec!["api-*".to_string()],
+            skip: vec!["api-v1".to_string()],
+            ..Default::default()
+        }
+        .apply(&mut only)
+        .unwrap();
+        assert_eq!(names(&only), vec!["api-v2"]);
+        assert_eq!(skipped.len(), 3);
+
+        let mut tagged = config();
+        SourceSelection {
+            only: vec!["changelog".to_string()],
+            tag: vec!["core".to_string()],
+            ..Default::default()
+        }
+        .apply(&mut tagged)
+        .unwrap();
+        assert_eq!(names(&tagged), vec!["api-v2", "docs", "changelog"]);
+
+        let err = SourceSelection {
+            only: vec!["nope".to_string()],
+            ..Default::default()
+        }
+        .apply(&mut config())
+        .unwrap_err();
+        assert!(err.contains("matches no source"), "{}", err);
+    }
+}
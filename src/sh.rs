@@ -2,17 +2,26 @@ use std::fs;
 use std::path::Path;
 use std::process::Command;
 
+/// Environment variable exposing the config file's directory to scripts
+pub const CONFIG_DIR_ENV: &str = "COPILOT_CONTEXT_CONFIG_DIR";
+
 /// Run a shell script in the specified destination directory
 ///
 /// # Arguments
 /// * `script` - The shell script content to execute
 /// * `dest` - The destination directory, relative to the current directory
+/// * `config_dir` - Directory of the config file, exported as `COPILOT_CONTEXT_CONFIG_DIR`
 /// * `verbose` - Whether to print verbose output
 ///
 /// # Returns
 /// * `Ok(())` on success
 /// * `Err(String)` with error message on failure
-pub fn run_script(script: &str, dest: &Path, verbose: bool) -> Result<(), String> {
+pub fn run_script(
+    script: &str,
+    dest: &Path,
+    config_dir: &Path,
+    verbose: bool,
+) -> Result<(), String> {
     if script.trim().is_empty() {
         return Err("Empty script provided".to_string());
     }
@@ -42,6 +51,7 @@ pub fn run_script(script: &str, dest: &Path, verbose: bool) -> Result<(), String
         .arg("-c")
         .arg(script)
         .current_dir(&target_dir)
+        .env(CONFIG_DIR_ENV, config_dir)
         .output()
         .map_err(|e| format!("Failed to execute script: {}", e))?;
 
@@ -88,7 +98,7 @@ mod tests {
         let script = "echo \'Hello, World!\' > test.txt";
         let dest_path = temp_dir.path();
 
-        let result = run_script(script, dest_path, false);
+        let result = run_script(script, dest_path, temp_dir.path(), false);
         assert!(result.is_ok());
 
         let file_path = dest_path.join("test.txt");
@@ -107,13 +117,26 @@ mod tests {
         let nested_path_buf = temp_dir.path().join(nested_dir);
         let script = "echo \'Hello from nested directory\' > test.txt";
 
-        let result = run_script(script, &nested_path_buf, false);
+        let result = run_script(script, &nested_path_buf, temp_dir.path(), false);
         assert!(result.is_ok());
 
         let file_path = nested_path_buf.join("test.txt");
         assert!(file_path.exists());
     }
 
+    #[test]
+    fn test_run_script_exposes_config_dir() {
+        let temp_dir = tempdir().unwrap();
+        let config_dir = temp_dir.path().join("project");
+        let script = format!("echo \"${}\" > config_dir.txt", CONFIG_DIR_ENV);
+
+        let result = run_script(&script, temp_dir.path(), &config_dir, false);
+        assert!(result.is_ok());
+
+        let contents = fs::read_to_string(temp_dir.path().join("config_dir.txt")).unwrap();
+        assert_eq!(contents.trim(), config_dir.to_str().unwrap());
+    }
+
     #[test]
     fn test_run_script_error() {
         let temp_dir = tempdir().unwrap();
@@ -121,7 +144,7 @@ mod tests {
         let script = "exit 1";
         let dest_path = temp_dir.path();
 
-        let result = run_script(script, dest_path, false);
+        let result = run_script(script, dest_path, temp_dir.path(), false);
         assert!(result.is_err());
     }
 
@@ -131,7 +154,7 @@ mod tests {
         let script = "";
         let dest_path = temp_dir.path();
 
-        let result = run_script(script, dest_path, false);
+        let result = run_script(script, dest_path, temp_dir.path(), false);
         assert!(result.is_err());
     }
 }
@@ -0,0 +1,74 @@
+use std::path::{Path, PathBuf};
+
+use crate::redact::{describe, Findings};
+
+/// A file removed from the context folder while processing a source
+#[derive(Debug, Clone)]
+pub struct Dropped {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Collects what happened during a run so it can be reported at the end
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    dropped: Vec<(String, Dropped)>,
+    redacted: Vec<(PathBuf, Findings)>,
+}
+
+impl RunSummary {
+    /// Record files dropped on behalf of `source`
+    pub fn record(&mut self, source: &str, dropped: Vec<Dropped>) {
+        self.dropped
+            .extend(dropped.into_iter().map(|d| (source.to_string(), d)));
+    }
+
+    /// Record files rewritten by the redaction pass
+    pub fn record_redacted(&mut self, redacted: Vec<(PathBuf, Findings)>) {
+        self.redacted.extend(redacted);
+    }
+
+    /// Print the summary, showing paths relative to the context folder `root`
+    pub fn print(&self, root: &Path) {
+        if !self.dropped.is_empty() {
+            println!("copilot-context: dropped {} file(s):", self.dropped.len());
+            for (source, dropped) in &self.dropped {
+                let rel = dropped.path.strip_prefix(root).unwrap_or(&dropped.path);
+                println!("  {} ({}): {}", rel.display(), source, dropped.reason);
+            }
+        }
+        if !self.redacted.is_empty() {
+            let total: usize = self.redacted.iter().flat_map(|(_, f)| f.values()).sum();
+            println!(
+                "copilot-context: redacted {} secret(s) in {} file(s):",
+                total,
+                self.redacted.len()
+            );
+            for (path, findings) in &self.redacted {
+                let rel = path.strip_prefix(root).unwrap_or(path);
+                println!("  {}: {}", rel.display(), describe(findings));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_tags_source() {
+        let mut summary = RunSummary::default();
+        summary.record(
+            "repo1",
+            vec![Dropped {
+                path: PathBuf::from("ctx/vendor/model.bin"),
+                reason: "binary content".to_string(),
+            }],
+        );
+        summary.record("empty", vec![]);
+        assert_eq!(summary.dropped.len(), 1);
+        assert_eq!(summary.dropped[0].0, "repo1");
+        assert_eq!(summary.dropped[0].1.reason, "binary content");
+    }
+}
@@ -0,0 +1,164 @@
+use base64::Engine;
+use clap::ValueEnum;
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Pre-tokenizer splitting text into words, numbers, punctuation runs and whitespace,
+/// in the style of GPT-2 (without the lookahead the `regex` crate doesn't support)
+const PRE_TOKENIZER: &str = r"'(?:s|t|re|ve|m|ll|d)| ?\p{L}+| ?\p{N}+| ?[^\s\p{L}\p{N}]+|\s+";
+
+/// Counts tokens the way a model would see them
+pub trait Tokenizer {
+    fn count(&self, text: &str) -> usize;
+}
+
+/// Which tokenizer to count with
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum TokenizerKind {
+    /// Estimate from byte and word counts, no vocabulary needed
+    #[default]
+    Heuristic,
+    /// Byte-pair encoding with an offline vocabulary file
+    Bpe,
+}
+
+/// Rough estimate: about four bytes per token, and never fewer than 4/3 tokens per word
+pub struct HeuristicTokenizer;
+
+impl Tokenizer for HeuristicTokenizer {
+    fn count(&self, text: &str) -> usize {
+        let by_bytes = text.len().div_ceil(4);
+        let by_words = (text.split_whitespace().count() * 4).div_ceil(3);
+        by_bytes.max(by_words)
+    }
+}
+
+/// Byte-pair encoding tokenizer using a tiktoken-style vocabulary
+/// (`<base64 token> <rank>` per line, e.g. `cl100k_base.tiktoken`)
+pub struct BpeTokenizer {
+    ranks: HashMap<Vec<u8>, u32>,
+    pre_tokenizer: Regex,
+}
+
+impl BpeTokenizer {
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read vocabulary '{}': {}", path.display(), e))?;
+        Self::from_vocab(&content)
+    }
+
+    pub fn from_vocab(vocab: &str) -> Result<Self, String> {
+        let mut ranks = HashMap::new();
+        for (line_no, line) in vocab.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid = || format!("invalid vocabulary entry on line {}", line_no + 1);
+            let (token, rank) = line.split_once(' ').ok_or_else(invalid)?;
+            let token = base64::engine::general_purpose::STANDARD
+                .decode(token)
+                .map_err(|_| invalid())?;
+            let rank: u32 = rank.trim().parse().map_err(|_| invalid())?;
+            ranks.insert(token, rank);
+        }
+        if ranks.is_empty() {
+            return Err("vocabulary is empty".to_string());
+        }
+        Ok(Self {
+            ranks,
+            pre_tokenizer: Regex::new(PRE_TOKENIZER).expect("pre-tokenizer is a valid regex"),
+        })
+    }
+
+    /// Number of tokens `piece` is merged into
+    fn count_piece(&self, piece: &[u8]) -> usize {
+        if self.ranks.contains_key(piece) {
+            return 1;
+        }
+        // Boundaries between parts; repeatedly merge the adjacent pair with the lowest rank
+        let mut bounds: Vec<usize> = (0..=piece.len()).collect();
+        loop {
+            let best = (0..bounds.len().saturating_sub(2))
+                .filter_map(|i| {
+                    self.ranks
+                        .get(&piece[bounds[i]..bounds[i + 2]])
+                        .map(|&rank| (rank, i))
+                })
+                .min();
+            match best {
+                Some((_, i)) => {
+                    bounds.remove(i + 1);
+                }
+                None => break,
+            }
+        }
+        bounds.len() - 1
+    }
+}
+
+impl Tokenizer for BpeTokenizer {
+    fn count(&self, text: &str) -> usize {
+        self.pre_tokenizer
+            .find_iter(text)
+            .map(|m| self.count_piece(m.as_str().as_bytes()))
+            .sum()
+    }
+}
+
+/// Build the tokenizer selected on the command line
+pub fn load_tokenizer(
+    kind: TokenizerKind,
+    vocab: Option<&Path>,
+) -> Result<Box<dyn Tokenizer>, String> {
+    match kind {
+        TokenizerKind::Heuristic => Ok(Box::new(HeuristicTokenizer)),
+        TokenizerKind::Bpe => {
+            let vocab = vocab.ok_or("the bpe tokenizer requires --vocab <FILE>")?;
+            Ok(Box::new(BpeTokenizer::from_file(vocab)?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vocab(tokens: &[&str]) -> String {
+        let engine = base64::engine::general_purpose::STANDARD;
+        let mut lines: Vec<String> = (0u8..=255)
+            .map(|b| format!("{} {}", engine.encode([b]), b))
+            .collect();
+        for (i, token) in tokens.iter().enumerate() {
+            lines.push(format!("{} {}", engine.encode(token), 256 + i));
+        }
+        lines.join("\n")
+    }
+
+    #[test]
+    fn test_heuristic_tokenizer() {
+        assert_eq!(HeuristicTokenizer.count(""), 0);
+        assert_eq!(HeuristicTokenizer.count("abcdefgh"), 2);
+        // Many short words count more than their bytes suggest
+        assert_eq!(HeuristicTokenizer.count("a b c d e f"), 8);
+    }
+
+    #[test]
+    fn test_bpe_tokenizer_merges_by_rank() {
+        let tokenizer = BpeTokenizer::from_vocab(&vocab(&["he", "ll", "hell", "hello"])).unwrap();
+        assert_eq!(tokenizer.count("hello"), 1);
+        // " hello" is one pre-token: " " + "hello"
+        assert_eq!(tokenizer.count(" hello"), 2);
+        // "help" -> "he" + "l" + "p"
+        assert_eq!(tokenizer.count("help"), 3);
+        assert_eq!(tokenizer.count("hello help"), 1 + 4);
+    }
+
+    #[test]
+    fn test_bpe_vocab_errors() {
+        assert!(BpeTokenizer::from_vocab("").is_err());
+        assert!(BpeTokenizer::from_vocab("not-base64! 1").is_err());
+        assert!(BpeTokenizer::from_vocab("aGk= x").is_err());
+        assert!(load_tokenizer(TokenizerKind::Bpe, None).is_err());
+    }
+}
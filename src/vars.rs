@@ -0,0 +1,208 @@
+use std::collections::BTreeMap;
+
+use crate::config::{ContextConfig, Source};
+
+/// Expand `${var}`, `${env:NAME}` and `${env:NAME:-default}` in `text`. `vars` holds the
+/// config's `[vars]`, `env` looks up environment variables. `$${` is a literal `${`.
+pub fn interpolate(
+    text: &str,
+    vars: &BTreeMap<String, String>,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    expand(text, vars, env, false)
+}
+
+/// Like `interpolate`, for `sh` scripts: a `${...}` that is neither `env:` nor one of
+/// `vars` is shell syntax and kept as written, so `${HOME}` reaches the shell.
+pub fn interpolate_script(
+    text: &str,
+    vars: &BTreeMap<String, String>,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    expand(text, vars, env, true)
+}
+
+fn expand(
+    text: &str,
+    vars: &BTreeMap<String, String>,
+    env: &dyn Fn(&str) -> Option<String>,
+    keep_unknown: bool,
+) -> Result<String, String> {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        if let Some(escaped) = after.strip_prefix("${") {
+            out.push_str("${");
+            rest = escaped;
+            continue;
+        }
+        let Some(body) = after.strip_prefix('{') else {
+            out.push('$');
+            rest = after;
+            continue;
+        };
+        let Some(end) = closing_brace(body) else {
+            if keep_unknown {
+                out.push('$');
+                rest = after;
+                continue;
+            }
+            return Err(format!("unclosed '${{' in '{}'", text));
+        };
+        let expr = &body[..end];
+        if keep_unknown && !expr.starts_with("env:") && !vars.contains_key(expr) {
+            out.push('$');
+            out.push_str(&after[..end + 2]);
+        } else {
+            out.push_str(&lookup(expr, vars, env)?);
+        }
+        rest = &body[end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Index of the `}` closing a placeholder, skipping over nested `${...}`
+fn closing_brace(body: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in body.char_indices() {
+        match c {
+            '{' if body[..i].ends_with('$') => depth += 1,
+            '}' if depth == 0 => return Some(i),
+            '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn lookup(
+    expr: &str,
+    vars: &BTreeMap<String, String>,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    match expr.strip_prefix("env:") {
+        Some(var) => {
+            let (name, default) = match var.split_once(":-") {
+                Some((name, default)) => (name, Some(default)),
+                None => (var, None),
+            };
+            match (env(name), default) {
+                (Some(value), _) => Ok(value),
+                // Defaults may themselves use variables
+                (None, Some(default)) => interpolate(default, vars, env),
+                (None, None) => Err(format!("environment variable '{}' is not set", name)),
+            }
+        }
+        None => vars
+            .get(expr)
+            .cloned()
+            .ok_or_else(|| format!("undefined variable '{}'", expr)),
+    }
+}
+
+/// Expand variables in every string field of `source`
+pub fn interpolate_source(
+    source: &mut Source,
+    vars: &BTreeMap<String, String>,
+    env: &dyn Fn(&str) -> Option<String>,
+) -> Result<(), String> {
+    let name = source.name().to_string();
+    for field in source.strings_mut() {
+        *field = interpolate(field, vars, env).map_err(|e| format!("source '{}': {}", name, e))?;
+    }
+    if let Source::Sh { script, .. } = source {
+        *script = interpolate_script(script, vars, env)
+            .map_err(|e| format!("source '{}': {}", name, e))?;
+    }
+    Ok(())
+}
+
+/// Expand variables in all sources, `[defaults]` and `exclude` of one config file,
+/// using that file's `[vars]`
+pub fn interpolate_config(config: &mut ContextConfig) -> Result<(), String> {
+    for source in &mut config.sources {
+        interpolate_source(source, &config.vars, &process_env)?;
+    }
+    let defaults = &mut config.defaults;
+    let fields = defaults
+        .files
+        .iter_mut()
+        .flatten()
+        .chain(&mut defaults.branch)
+        .chain(defaults.headers.0.values_mut())
+        .chain(&mut config.exclude);
+    for field in fields {
+        *field = interpolate(field, &config.vars, &process_env)
+            .map_err(|e| format!("[defaults]: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Environment lookup used outside of tests
+fn process_env(name: &str) -> Option<String> {
+    std::env::var(name).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(name: &str) -> Option<String> {
+        (name == "CI_BRANCH").then(|| "release".to_string())
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let vars = BTreeMap::from([
+            ("version".to_string(), "1.2".to_string()),
+            ("base".to_string(), "https://example.com".to_string()),
+        ]);
+        assert_eq!(
+            interpolate("${base}/v${version}/spec.yaml", &vars, &env).unwrap(),
+            "https://example.com/v1.2/spec.yaml"
+        );
+        assert_eq!(
+            interpolate("${env:CI_BRANCH}", &vars, &env).unwrap(),
+            "release"
+        );
+        assert_eq!(
+            interpolate("${env:MISSING:-main}", &vars, &env).unwrap(),
+            "main"
+        );
+        assert_eq!(
+            interpolate("${env:MISSING:-v${version}}", &vars, &env).unwrap(),
+            "v1.2"
+        );
+        assert_eq!(
+            interpolate("$HOME costs $5, $${literal}", &vars, &env).unwrap(),
+            "$HOME costs $5, ${literal}"
+        );
+
+        let err = interpolate("${nope}", &vars, &env).unwrap_err();
+        assert!(err.contains("undefined variable 'nope'"), "{}", err);
+        assert!(interpolate("${env:MISSING}", &vars, &env).is_err());
+        assert!(interpolate("${version", &vars, &env).is_err());
+    }
+
+    #[test]
+    fn test_interpolate_script_keeps_shell_syntax() {
+        let vars = BTreeMap::from([("version".to_string(), "1.2".to_string())]);
+        assert_eq!(
+            interpolate_script(
+                "echo ${HOME} ${name:-x} ${#list} v${version} ${env:CI_BRANCH} $${version}",
+                &vars,
+                &env
+            )
+            .unwrap(),
+            "echo ${HOME} ${name:-x} ${#list} v1.2 release ${version}"
+        );
+        assert_eq!(
+            interpolate_script("awk '{print $1}' ${unclosed", &vars, &env).unwrap(),
+            "awk '{print $1}' ${unclosed"
+        );
+        assert!(interpolate_script("${env:MISSING}", &vars, &env).is_err());
+    }
+}